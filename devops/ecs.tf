@@ -63,17 +63,39 @@ module "public-apps-ecs-service" {
   task_exec_iam_statements = []
   task_exec_ssm_param_arns = []
 
-  container_definitions = {
+  # Task-wide volumes, shared by the app and its init containers.
+  volume = { for name, path in try(each.value.volumes, {}) : name => {} }
+
+  container_definitions = merge({
+    for name, init in try(each.value.init_containers, {}) : name => {
+      essential                 = false
+      enable_cloudwatch_logging = true
+      image                     = init.image
+      command                   = init.command
+      secrets                   = try(init.secrets, [])
+      user                      = "0"
+      readonly_root_filesystem  = false
+      mountPoints = [
+        for volume, path in try(each.value.volumes, {}) : { sourceVolume = volume, containerPath = path }
+      ]
+    }
+    }, {
     (each.value.container_name) = {
       cpu                       = each.value.cpu
       memory                    = each.value.memory
       essential                 = true
       enable_cloudwatch_logging = true
       image                     = "${each.value.app_image}:${each.value.image_tag}"
-      mountPoints               = []
-      volumesFrom               = []
-      ulimits                   = try(each.value.ulimits, [])
-      user                      = try(each.value.user, "1000")
+      mountPoints = [
+        for volume, path in try(each.value.volumes, {}) : { sourceVolume = volume, containerPath = path, readOnly = true }
+      ]
+      volumesFrom = []
+      # Init containers must have finished successfully before the app starts.
+      dependsOn = [
+        for name in keys(try(each.value.init_containers, {})) : { containerName = name, condition = "SUCCESS" }
+      ]
+      ulimits = try(each.value.ulimits, [])
+      user    = try(each.value.user, "1000")
       portMappings = [
         {
           name          = each.value.container_name
@@ -96,7 +118,7 @@ module "public-apps-ecs-service" {
         timeout     = 5
       }
     }
-  }
+  })
 
   subnet_ids = local.private_subnet_ids
 
@@ -33,15 +33,38 @@ locals {
       # Health check for ALB
       health_check_path = "/healthz"
       # Health check for ECS task
-      health_check_target   = "http://localhost:3000/healthz"
-      task_exec_secret_arns = []
+      health_check_target = "http://localhost:3000/healthz"
+      task_exec_secret_arns = [
+        aws_secretsmanager_secret.rng_server_keystore.arn,
+        aws_secretsmanager_secret.rng_server_keystore_password.arn,
+      ]
+      # The server only reads its keystore password from a file, so an
+      # init container copies the keystore and password from Secrets
+      # Manager into a volume shared with the server, readable by its user
+      # alone.
+      volumes = {
+        keystore = "/run/rng-server"
+      }
+      init_containers = {
+        keystore = {
+          image = "public.ecr.aws/docker/library/busybox:1.36"
+          command = [
+            "sh", "-c",
+            "umask 077 && printf %s \"$KEYSTORE\" > /run/rng-server/keystore.json && printf %s \"$KEYSTORE_PASSWORD\" > /run/rng-server/keystore-password && chown 1000 /run/rng-server/keystore.json /run/rng-server/keystore-password"
+          ]
+          secrets = [
+            { name = "KEYSTORE", valueFrom = aws_secretsmanager_secret.rng_server_keystore.arn },
+            { name = "KEYSTORE_PASSWORD", valueFrom = aws_secretsmanager_secret.rng_server_keystore_password.arn },
+          ]
+        }
+      }
       environment = [
         { name = "HEALTH_CHECK_APP_VERSION", value = local.rng_server_tag },
         { name = "HEALTH_CHECK_SLACK_WEBHOOK", value = var.HEALTH_CHECK_SLACK_WEBHOOK },
         { name = "HEALTH_CHECK_IMAGE_URL", value = local.health_check_image_url },
         { name = "HEALTH_CHECK_NOTIFICATION_CONTEXT", value = local.health_check_notfification_context },
-        { name = "RARE_EVO_SIGNING_KEY", value = var.RNG_SERVER_SIGNING_KEY },
-        { name = "RARE_EVO_HMAC_SECRET", value = var.RNG_SERVER_HMAC_SECRET },
+        { name = "RARE_EVO_KEYSTORE", value = "/run/rng-server/keystore.json" },
+        { name = "RARE_EVO_KEYSTORE_PASSWORD_FILE", value = "/run/rng-server/keystore-password" },
         { name = "RARE_EVO_BIND", value = "0.0.0.0:3000" },
       ]
     }
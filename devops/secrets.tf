@@ -0,0 +1,13 @@
+# The RNG server's keystore, written by `rng-server generate --keystore`,
+# and its password. Only the secrets themselves are managed here: put
+# their values with `aws secretsmanager put-secret-value`, so they never
+# end up in the Terraform state or the task definition.
+resource "aws_secretsmanager_secret" "rng_server_keystore" {
+  name        = "${local.name}/rng-server/keystore"
+  description = "Encrypted keystore holding the RNG server's signing keys and HMAC secret"
+}
+
+resource "aws_secretsmanager_secret" "rng_server_keystore_password" {
+  name        = "${local.name}/rng-server/keystore-password"
+  description = "Password for the RNG server's keystore"
+}
@@ -4,18 +4,6 @@ variable "HEALTH_CHECK_SLACK_WEBHOOK" {
   sensitive   = true
 }
 
-variable "RNG_SERVER_SIGNING_KEY" {
-  description = "The private key used to sign tokens for the RNG server."
-  type        = string
-  sensitive   = true
-}
-
-variable "RNG_SERVER_HMAC_SECRET" {
-  description = "The HMAC secret used for message authentication in the RNG server."
-  type        = string
-  sensitive   = true
-}
-
 variable "KOLME_POSTGRES_STORE" {
   description = "Postgres credential for Postgres Store"
   type        = string
@@ -12,7 +12,7 @@ use rust_decimal::prelude::Zero;
 
 use crate::{
     app::{ASSET_ID, GuessGame},
-    indexer::{IndexerStateLock, LeaderboardEntry, RoundResults},
+    indexer::{IndexerStateLock, LeaderboardEntry},
     time::GuessTimestamp,
 };
 
@@ -49,6 +49,10 @@ struct GuessGameData {
     last_winner: Option<LastWinner>,
     /// The top 10 participants of all time.
     leaderboard: Vec<LeaderboardEntry>,
+    /// Lifetime rake taken by the house.
+    house_revenue: Decimal,
+    /// Jackpot for the next exact guess.
+    jackpot: Decimal,
 }
 
 #[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
@@ -56,23 +60,27 @@ struct LastWinner {
     finished: Timestamp,
     number: u8,
     winnings: BTreeMap<AccountId, Decimal>,
+    jackpot: BTreeMap<AccountId, Decimal>,
 }
 
 async fn guess_game_data(State(route_state): State<RouteState>) -> Json<GuessGameData> {
     let RouteState { kolme, indexer } = route_state;
     let indexer_state = indexer.read().await;
-    let current_round = GuessTimestamp::after(Timestamp::now());
-    let last_winner = indexer_state.results.last_key_value().map(
-        |(finished, RoundResults { number, winnings })| LastWinner {
-            finished: finished.into(),
-            number: *number,
-            winnings: winnings.clone(),
-        },
-    );
+    let kolme_r = kolme.read();
+    let round_period = kolme_r.get_app_state().round_period;
+    let current_round = GuessTimestamp::after(Timestamp::now(), round_period);
+    let last_winner = indexer_state
+        .results
+        .last_key_value()
+        .map(|(finished, results)| LastWinner {
+            finished: finished.to_timestamp(round_period),
+            number: results.number,
+            winnings: results.winnings.clone(),
+            jackpot: results.jackpot.clone(),
+        });
     Json(GuessGameData {
-        current_round_finishes: current_round.into(),
-        current_bets: kolme
-            .read()
+        current_round_finishes: current_round.to_timestamp(round_period),
+        current_bets: kolme_r
             .get_app_state()
             .pending_wagers
             .get(&current_round)
@@ -81,6 +89,13 @@ async fn guess_game_data(State(route_state): State<RouteState>) -> Json<GuessGam
             }),
         last_winner,
         leaderboard: indexer_state.leaderboard.clone(),
+        house_revenue: indexer_state.house_revenue,
+        jackpot: kolme_r
+            .get_app_state()
+            .jackpot
+            .get(&ASSET_ID)
+            .cloned()
+            .unwrap_or_default(),
     })
 }
 
@@ -95,6 +110,7 @@ async fn account_data(
     Path(pubkey): Path<PublicKey>,
 ) -> Json<AccountData> {
     let kolme_r = route_state.kolme.read();
+    let round_period = kolme_r.get_app_state().round_period;
     let Some((account_id, account)) = kolme_r
         .get_framework_state()
         .get_accounts()
@@ -118,7 +134,7 @@ async fn account_data(
         .get(&account_id)
         .map_or_else(BTreeMap::new, |orig| {
             orig.iter()
-                .map(|(timestamp, guesses)| (timestamp.into(), guesses.clone()))
+                .map(|(timestamp, guesses)| (timestamp.to_timestamp(round_period), guesses.clone()))
                 .collect()
         });
     Json(AccountData { funds, bet_history })
@@ -2,18 +2,71 @@ use std::collections::{BTreeMap, HashMap};
 
 use anyhow::{Context, Result};
 use kolme::*;
+use rng_protocol::{
+    RngCommitment, RngKeySet, RngResult, RngShare, derive_number, seed_input, threshold, vrf,
+};
+use sha2::{Digest, Sha256};
 
 use crate::{
-    rng_server::RngResult,
-    state::{GuessState, Wager},
-    time::GuessTimestamp,
+    limits::BetLimits,
+    rng_server::signature_hash,
+    state::{GuessState, MAX_BPS, Rake, RngKey, RngQuorum, Wager, rng_verifying_key, share_bps},
+    time::{GuessTimestamp, RoundPeriod},
 };
 
 /// The application data structure itself.
 #[derive(Clone)]
 pub struct GuessGame {
     genesis_info: GenesisInfo,
-    rng_public_key: PublicKey,
+    rng: RngSettings,
+    settings: GameSettings,
+}
+
+/// How the game gets its random numbers, fixed at genesis.
+#[derive(Clone)]
+pub struct RngSettings {
+    pub public_key: PublicKey,
+    pub beacon: bool,
+    pub commitments: bool,
+    pub vrf: bool,
+    pub quorum: Option<RngQuorum>,
+    /// Named stream on the RNG server, [None] for its default stream.
+    pub stream: Option<String>,
+    pub round_period: RoundPeriod,
+}
+
+/// The house's rules for bets and payouts, fixed at genesis.
+#[derive(Clone)]
+pub struct GameSettings {
+    /// Cut taken from each settled pot, [None] to pay out all of it.
+    rake: Option<Rake>,
+    /// Share of each settled pot going to the jackpot, in basis points.
+    ///
+    /// The jackpot grows until a round where some guess is exactly the
+    /// number, and is then split between those guesses by wager.
+    jackpot_bps: Option<u16>,
+    bet_limits: BetLimits,
+}
+
+impl GameSettings {
+    pub fn new(
+        rake: Option<Rake>,
+        jackpot_bps: Option<u16>,
+        bet_limits: BetLimits,
+    ) -> anyhow::Result<Self> {
+        let total = u32::from(rake.as_ref().map_or(0, |rake| rake.bps))
+            + u32::from(jackpot_bps.unwrap_or_default());
+        anyhow::ensure!(
+            total <= u32::from(MAX_BPS),
+            "Rake and jackpot together take {total} basis points, more than the whole pot"
+        );
+        bet_limits.validate()?;
+        Ok(GameSettings {
+            rake,
+            jackpot_bps,
+            bet_limits,
+        })
+    }
 }
 
 /// All the different actions a client can perform on this app.
@@ -24,14 +77,67 @@ pub enum GuessMessage {
     GrabFunds {},
     /// Place a bet for the current round of betting.
     ///
-    /// Note: this will fail if you have insufficient funds!
+    /// Note: this will fail if you have insufficient funds, or the bet
+    /// breaks the game's [BetLimits]!
     PlaceBet { guess: u8, amount: Decimal },
+    /// Place several bets for the current round of betting at once, as `(guess, amount)`.
+    ///
+    /// Either all of them are placed or none are, if their total is more
+    /// than your funds or any of them breaks the game's [BetLimits].
+    PlaceBets { bets: Vec<(u8, Decimal)> },
+    /// Take back your bets on a guess, while their round is still open.
+    ///
+    /// The stake is refunded in full.
+    CancelBet {
+        timestamp: GuessTimestamp,
+        guess: u8,
+    },
+    /// Replace your bets on a guess with a bet on `new_guess`, while their round is still open.
+    ///
+    /// Same as [GuessMessage::CancelBet] followed by [GuessMessage::PlaceBet],
+    /// but in one transaction, so the old bets stand if the new one fails.
+    AmendBet {
+        timestamp: GuessTimestamp,
+        guess: u8,
+        new_guess: u8,
+        amount: Decimal,
+    },
     /// Settle a round of betting with the given result.
     ///
     /// Note that this is an unprivileged message! Security
     /// is provided via the signature on the result, proving
     /// that it came from the official RNG server.
-    SettleBet { result: SignedTaggedJson<RngResult> },
+    ///
+    /// When the RNG server runs in beacon mode, the previous round's
+    /// result must be provided as well so the chain can be checked,
+    /// except for the beacon's genesis round.
+    SettleBet {
+        result: SignedTaggedJson<RngResult>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        previous: Option<SignedTaggedJson<RngResult>>,
+    },
+    /// Settle a round of betting from the shares of independent RNG operators.
+    ///
+    /// Used instead of [GuessMessage::SettleBet] in threshold mode. Each
+    /// share must be signed by a different operator in the quorum, and
+    /// there must be at least as many as the threshold. Any such set of
+    /// shares settles the round with the same number.
+    SettleBetShares {
+        shares: Vec<SignedTaggedJson<RngShare>>,
+    },
+    /// Record the RNG server's commitment to an upcoming round.
+    ///
+    /// Like [GuessMessage::SettleBet] this is unprivileged, the commitment
+    /// is signed by the RNG server. It must arrive before betting opens
+    /// for the round.
+    RecordCommitment {
+        commitment: SignedTaggedJson<RngCommitment>,
+    },
+    /// Replace the RNG server's key set, used for rotating keys.
+    ///
+    /// Also unprivileged: the key set must be signed by the key that's
+    /// currently trusted for the round it was issued in.
+    SetRngKeys { keys: SignedTaggedJson<RngKeySet> },
 }
 
 /// App specific log messages.
@@ -48,6 +154,19 @@ pub enum GuessGameLog {
         guess: u8,
         amount: Decimal,
     },
+    /// Several bets placed together by [GuessMessage::PlaceBets], as `(guess, amount)`.
+    Wagers {
+        account: AccountId,
+        timestamp: GuessTimestamp,
+        bets: Vec<(u8, Decimal)>,
+    },
+    /// An account's bets on a guess were cancelled and refunded.
+    WagerCancelled {
+        account: AccountId,
+        timestamp: GuessTimestamp,
+        guess: u8,
+        amount: Decimal,
+    },
     NewWinner {
         finished: GuessTimestamp,
         number: u8,
@@ -57,12 +176,24 @@ pub enum GuessGameLog {
         winner: AccountId,
         amount: Decimal,
     },
+    /// A share of the jackpot, won by guessing the number exactly.
+    Jackpot {
+        finished: GuessTimestamp,
+        winner: AccountId,
+        amount: Decimal,
+    },
+    /// The house's cut of a settled pot, minted to the treasury.
+    Rake {
+        finished: GuessTimestamp,
+        treasury: AccountId,
+        amount: Decimal,
+    },
 }
 
 impl GuessGame {
     pub const CODE_VERSION: &str = "v1.0.0";
 
-    pub fn new(validator_public_key: PublicKey, rng_public_key: PublicKey) -> Self {
+    pub fn new(validator_public_key: PublicKey, rng: RngSettings, settings: GameSettings) -> Self {
         GuessGame {
             genesis_info: GenesisInfo {
                 kolme_ident: "RareEvo 2025 Kolme App - Guessing Game".to_owned(),
@@ -76,7 +207,8 @@ impl GuessGame {
                 chains: ConfiguredChains::default(),
                 version: Self::CODE_VERSION.to_owned(),
             },
-            rng_public_key,
+            rng,
+            settings,
         }
     }
 }
@@ -93,10 +225,28 @@ impl KolmeApp for GuessGame {
     }
 
     fn new_state(&self) -> Result<Self::State> {
+        let mut rng_keys = MerkleVec::new();
+        rng_keys.push(RngKey {
+            public_key: self.rng.public_key,
+            valid_from: None,
+            valid_until: None,
+        });
         Ok(GuessState {
-            rng_public_key: self.rng_public_key,
+            rng_keys,
+            rng_keys_issued: None,
+            rng_beacon: self.rng.beacon,
+            rng_commitments: self.rng.commitments,
+            rng_vrf: self.rng.vrf,
+            rng_quorum: self.rng.quorum.clone(),
+            rng_stream: self.rng.stream.clone(),
+            round_period: self.rng.round_period,
+            rake: self.settings.rake.clone(),
+            jackpot_bps: self.settings.jackpot_bps,
+            jackpot: MerkleMap::new(),
+            bet_limits: self.settings.bet_limits.clone(),
             received_funds: MerkleMap::new(),
             pending_wagers: MerkleMap::new(),
+            commitments: MerkleMap::new(),
         })
     }
 
@@ -112,8 +262,32 @@ impl KolmeApp for GuessGame {
             GuessMessage::PlaceBet { guess, amount } => {
                 place_bet(ctx, *guess, *amount)?;
             }
-            GuessMessage::SettleBet { result } => {
-                settle_bet(ctx, result)?;
+            GuessMessage::PlaceBets { bets } => {
+                place_bets(ctx, bets)?;
+            }
+            GuessMessage::CancelBet { timestamp, guess } => {
+                cancel_bet(ctx, *timestamp, *guess)?;
+            }
+            GuessMessage::AmendBet {
+                timestamp,
+                guess,
+                new_guess,
+                amount,
+            } => {
+                cancel_bet(ctx, *timestamp, *guess)?;
+                place_bet(ctx, *new_guess, *amount)?;
+            }
+            GuessMessage::SettleBet { result, previous } => {
+                settle_bet(ctx, result, previous.as_ref())?;
+            }
+            GuessMessage::SettleBetShares { shares } => {
+                settle_bet_shares(ctx, shares)?;
+            }
+            GuessMessage::RecordCommitment { commitment } => {
+                record_commitment(ctx, commitment)?;
+            }
+            GuessMessage::SetRngKeys { keys } => {
+                set_rng_keys(ctx, keys)?;
             }
         }
 
@@ -136,17 +310,110 @@ fn grab_funds(ctx: &mut ExecutionContext<'_, GuessGame>) -> Result<()> {
 
 fn place_bet(ctx: &mut ExecutionContext<'_, GuessGame>, guess: u8, amount: Decimal) -> Result<()> {
     let sender = ctx.get_sender_id();
-    let timestamp = GuessTimestamp::after(ctx.block_time());
-    ctx.burn_asset(ASSET_ID, sender, amount)?;
-    ctx.state_mut()
+    let timestamp = record_wagers(ctx, &[(guess, amount)])?;
+    ctx.log_json(&GuessGameLog::Wager {
+        account: sender,
+        timestamp,
+        guess,
+        amount,
+    })?;
+    Ok(())
+}
+
+fn place_bets(ctx: &mut ExecutionContext<'_, GuessGame>, bets: &[(u8, Decimal)]) -> Result<()> {
+    anyhow::ensure!(!bets.is_empty(), "No bets provided");
+    let sender = ctx.get_sender_id();
+    let timestamp = record_wagers(ctx, bets)?;
+    ctx.log_json(&GuessGameLog::Wagers {
+        account: sender,
+        timestamp,
+        bets: bets.to_vec(),
+    })?;
+    Ok(())
+}
+
+/// Record the sender's bets on the current round, returning the round.
+///
+/// Checks every bet against the [BetLimits] and burns their total stake
+/// before recording any of them.
+fn record_wagers(
+    ctx: &mut ExecutionContext<'_, GuessGame>,
+    bets: &[(u8, Decimal)],
+) -> Result<GuessTimestamp> {
+    let sender = ctx.get_sender_id();
+    let timestamp = GuessTimestamp::after(ctx.block_time(), ctx.app_state().round_period);
+    if ctx.app_state().rng_commitments {
+        anyhow::ensure!(
+            ctx.app_state().commitments.get(&timestamp).is_some(),
+            "No RNG commitment recorded for round {timestamp}, betting is not open yet"
+        );
+    }
+    let (mut stake, mut wagers) = ctx
+        .app_state()
         .pending_wagers
-        .get_or_default(timestamp)
-        .push(Wager {
+        .get(&timestamp)
+        .into_iter()
+        .flat_map(|wagers| wagers.iter())
+        .filter(|wager| wager.account == sender)
+        .fold((Decimal::ZERO, 0), |(stake, wagers), wager| {
+            (stake + wager.amount, wagers + 1)
+        });
+    let mut total = Decimal::ZERO;
+    for &(_guess, amount) in bets {
+        ctx.app_state().bet_limits.check(amount, stake, wagers)?;
+        stake += amount;
+        wagers += 1;
+        total += amount;
+    }
+    ctx.burn_asset(ASSET_ID, sender, total)?;
+    let round = ctx.state_mut().pending_wagers.get_or_default(timestamp);
+    for &(guess, amount) in bets {
+        round.push(Wager {
             account: sender,
             guess,
             amount,
         });
-    ctx.log_json(&GuessGameLog::Wager {
+    }
+    Ok(timestamp)
+}
+
+/// Remove the sender's bets on a guess from an open round, and refund them.
+fn cancel_bet(
+    ctx: &mut ExecutionContext<'_, GuessGame>,
+    timestamp: GuessTimestamp,
+    guess: u8,
+) -> Result<()> {
+    let sender = ctx.get_sender_id();
+    let current = GuessTimestamp::after(ctx.block_time(), ctx.app_state().round_period);
+    anyhow::ensure!(
+        timestamp >= current,
+        "Round {timestamp} is closed, its bets can no longer be changed"
+    );
+    let state = ctx.app_state_mut();
+    let (cancelled, kept): (Vec<_>, Vec<_>) = state
+        .pending_wagers
+        .get(&timestamp)
+        .with_context(|| format!("No bets placed for round {timestamp}"))?
+        .iter()
+        .cloned()
+        .partition(|wager| wager.account == sender && wager.guess == guess);
+    anyhow::ensure!(
+        !cancelled.is_empty(),
+        "No bets on {guess} by {sender} for round {timestamp}"
+    );
+    // Drop the round altogether once it's empty, so nobody tries to settle it.
+    if kept.is_empty() {
+        state.pending_wagers.remove(&timestamp);
+    } else {
+        let mut wagers = MerkleVec::new();
+        for wager in kept {
+            wagers.push(wager);
+        }
+        state.pending_wagers.insert(timestamp, wagers);
+    }
+    let amount = cancelled.iter().map(|wager| wager.amount).sum();
+    ctx.mint_asset(ASSET_ID, sender, amount)?;
+    ctx.log_json(&GuessGameLog::WagerCancelled {
         account: sender,
         timestamp,
         guess,
@@ -158,18 +425,116 @@ fn place_bet(ctx: &mut ExecutionContext<'_, GuessGame>, guess: u8, amount: Decim
 fn settle_bet(
     ctx: &mut ExecutionContext<'_, GuessGame>,
     result: &SignedTaggedJson<RngResult>,
+    previous: Option<&SignedTaggedJson<RngResult>>,
 ) -> Result<()> {
+    let RngResult {
+        number,
+        timestamp,
+        stream,
+        seed,
+        min,
+        max,
+        ..
+    } = result.message.as_inner();
+    let timestamp = GuessTimestamp::from(*timestamp);
+    check_rng_stream(ctx.app_state(), stream.as_deref())?;
+    anyhow::ensure!(
+        ctx.app_state().rng_quorum.is_none(),
+        "RNG runs in threshold mode, rounds must be settled from operator shares"
+    );
+    // Payouts assume the full u32 range, a narrower one would change the odds
+    anyhow::ensure!(
+        min.is_none() && max.is_none(),
+        "RNG result was drawn from a restricted range"
+    );
     let pubkey = result.verify_signature()?;
-    anyhow::ensure!(pubkey == ctx.app_state().rng_public_key);
-    let RngResult { number, timestamp } = result.message.as_inner();
-    let timestamp = GuessTimestamp::try_from(*timestamp)?;
+    check_rng_signer(ctx.app_state(), timestamp, pubkey)?;
+    if ctx.app_state().rng_beacon {
+        check_beacon_link(ctx.app_state(), result.message.as_inner(), previous)?;
+    }
+    if ctx.app_state().rng_vrf {
+        check_vrf_proof(pubkey, result.message.as_inner())?;
+    }
+    if ctx.app_state().rng_commitments {
+        let commitment = ctx
+            .app_state_mut()
+            .commitments
+            .remove(&timestamp)
+            .context("No RNG commitment recorded for given timestamp")?
+            .1;
+        check_reveal(&commitment, *number, seed.as_deref())?;
+    }
+    pay_out(ctx, timestamp, *number)
+}
+
+/// Settle a round in threshold mode, combining the shares of the RNG operators.
+fn settle_bet_shares(
+    ctx: &mut ExecutionContext<'_, GuessGame>,
+    shares: &[SignedTaggedJson<RngShare>],
+) -> Result<()> {
+    let quorum = ctx
+        .app_state()
+        .rng_quorum
+        .as_ref()
+        .context("RNG does not run in threshold mode, rounds must be settled from a result")?;
+    let timestamp = shares
+        .first()
+        .context("No RNG shares provided")?
+        .message
+        .as_inner()
+        .timestamp;
+    let mut contributions = Vec::<(u8, k256::ecdsa::VerifyingKey, Vec<u8>)>::new();
+    for share in shares {
+        let signer = share.verify_signature()?;
+        let RngShare {
+            share,
+            timestamp: share_timestamp,
+            stream,
+        } = share.message.as_inner();
+        check_rng_stream(ctx.app_state(), stream.as_deref())?;
+        anyhow::ensure!(
+            *share_timestamp == timestamp,
+            "RNG shares for different rounds {timestamp} and {share_timestamp}"
+        );
+        let index = quorum
+            .index_of(&signer)
+            .with_context(|| format!("RNG share signed by {signer}, which is not in the quorum"))?;
+        contributions.push((index, rng_verifying_key(&signer)?, hex::decode(share)?));
+    }
+    // Any `threshold` valid shares combine to the same seed, so whoever
+    // settles the round can't pick between outcomes.
+    let alpha = seed_input(ctx.app_state().rng_stream.as_deref(), timestamp)?;
+    let seed = threshold::combine(
+        &quorum.group_key()?,
+        quorum.threshold,
+        &alpha,
+        &contributions
+            .iter()
+            .map(|(index, public_key, proof)| threshold::Share {
+                index: *index,
+                public_key: *public_key,
+                proof,
+            })
+            .collect::<Vec<_>>(),
+    )
+    .with_context(|| format!("Unable to combine RNG shares for round {timestamp}"))?;
+    let number = derive_number(&seed, None);
+    pay_out(ctx, GuessTimestamp::from(timestamp), number)
+}
+
+/// Pay out the wagers for a round once its random number is known.
+fn pay_out(
+    ctx: &mut ExecutionContext<'_, GuessGame>,
+    timestamp: GuessTimestamp,
+    number: u32,
+) -> Result<()> {
     let wagers = ctx
         .app_state_mut()
         .pending_wagers
         .remove(&timestamp)
         .context("No pending wagers for given timestamp found")?
         .1;
-    let number = (*number % 256) as u8;
+    let number = (number % 256) as u8;
 
     let mut total_bet = Decimal::ZERO;
     let mut winning_weights = HashMap::<_, Decimal>::new();
@@ -207,8 +572,52 @@ fn settle_bet(
         number,
     })?;
 
+    // The house takes its cut first, the winners split the rest.
+    let mut pot = total_bet;
+    let rake = ctx
+        .app_state()
+        .rake
+        .as_ref()
+        .map(|rake| (rake.treasury, rake.of(total_bet)))
+        .filter(|(_, amount)| *amount > Decimal::ZERO);
+    if let Some((treasury, amount)) = rake {
+        ctx.log_json(&GuessGameLog::Rake {
+            finished: timestamp,
+            treasury,
+            amount,
+        })?;
+        ctx.mint_asset(ASSET_ID, treasury, amount)?;
+        pot -= amount;
+    }
+    // Then a share of every wager goes to the jackpot.
+    if let Some(bps) = ctx.app_state().jackpot_bps {
+        let contribution = share_bps(total_bet, bps);
+        *ctx.app_state_mut().jackpot.get_or_default(ASSET_ID) += contribution;
+        pot -= contribution;
+    }
+
+    // Exact hits also split the jackpot, including this round's contribution.
+    if winning_distance == 0 {
+        let jackpot = ctx
+            .app_state_mut()
+            .jackpot
+            .remove(&ASSET_ID)
+            .map_or(Decimal::ZERO, |(_, jackpot)| jackpot);
+        if jackpot > Decimal::ZERO {
+            for (winner, weight) in &winning_weights {
+                let amount = jackpot * *weight / total_weight;
+                ctx.log_json(&GuessGameLog::Jackpot {
+                    finished: timestamp,
+                    winner: *winner,
+                    amount,
+                })?;
+                ctx.mint_asset(ASSET_ID, *winner, amount)?;
+            }
+        }
+    }
+
     for (winner, weight) in winning_weights {
-        let amount = total_bet * weight / total_weight;
+        let amount = pot * weight / total_weight;
         ctx.log_json(&GuessGameLog::Winnings {
             finished: timestamp,
             winner,
@@ -220,3 +629,226 @@ fn settle_bet(
 
     Ok(())
 }
+
+/// Check that an RNG server message for the given round is signed by the key valid for that round.
+fn check_rng_signer(
+    state: &GuessState,
+    timestamp: GuessTimestamp,
+    signer: PublicKey,
+) -> Result<()> {
+    let expected = state
+        .rng_key_for(timestamp)
+        .with_context(|| format!("No RNG key valid for round {timestamp}"))?;
+    anyhow::ensure!(
+        signer == expected,
+        "Round {timestamp} must be signed by RNG key {expected}, but was signed by {signer}"
+    );
+    Ok(())
+}
+
+/// Check that an RNG server message is for this game's stream.
+///
+/// Otherwise results for another game sharing the RNG server could be replayed here.
+fn check_rng_stream(state: &GuessState, stream: Option<&str>) -> Result<()> {
+    let expected = state.rng_stream.as_deref();
+    anyhow::ensure!(
+        stream == expected,
+        "RNG message is for stream {}, expected {}",
+        stream.unwrap_or("(default)"),
+        expected.unwrap_or("(default)")
+    );
+    Ok(())
+}
+
+/// Check that a beacon result is chained to the round right before it.
+///
+/// The beacon's genesis round has no predecessor, so a signed result
+/// without a link is accepted on its own.
+fn check_beacon_link(
+    state: &GuessState,
+    result: &RngResult,
+    previous: Option<&SignedTaggedJson<RngResult>>,
+) -> Result<()> {
+    let Some(expected) = result.previous_signature_hash.as_deref() else {
+        anyhow::ensure!(
+            previous.is_none(),
+            "Round {} is the beacon's genesis round, it has no previous round",
+            result.timestamp
+        );
+        return Ok(());
+    };
+    let previous = previous.context("Beacon mode requires the previous round's result")?;
+    check_rng_signer(
+        state,
+        GuessTimestamp::from(previous.message.as_inner().timestamp),
+        previous.verify_signature()?,
+    )?;
+    anyhow::ensure!(
+        previous.message.as_inner().timestamp + 1 == result.timestamp,
+        "Previous round {} does not immediately precede round {}",
+        previous.message.as_inner().timestamp,
+        result.timestamp
+    );
+    let actual = signature_hash(&previous.signature)?;
+    anyhow::ensure!(
+        actual == expected,
+        "Beacon link mismatch for round {}: expected {expected}, got {actual}",
+        result.timestamp
+    );
+    Ok(())
+}
+
+/// Check the ECVRF proof on a result, showing its number was derived honestly.
+fn check_vrf_proof(rng_public_key: PublicKey, result: &RngResult) -> Result<()> {
+    let proof = hex::decode(
+        result
+            .proof
+            .as_deref()
+            .context("RNG result is missing its VRF proof")?,
+    )?;
+    let public_key = rng_verifying_key(&rng_public_key)?;
+    let alpha = seed_input(result.stream.as_deref(), result.timestamp)?;
+    let output = vrf::verify(&public_key, &alpha, &proof)?;
+    if let Some(seed) = &result.seed {
+        anyhow::ensure!(
+            *seed == hex::encode(output),
+            "RNG seed does not match the VRF output"
+        );
+    }
+    let expected = derive_number(&output, None);
+    anyhow::ensure!(
+        result.number == expected,
+        "RNG number {} does not match the VRF output",
+        result.number
+    );
+    Ok(())
+}
+
+fn record_commitment(
+    ctx: &mut ExecutionContext<'_, GuessGame>,
+    commitment: &SignedTaggedJson<RngCommitment>,
+) -> Result<()> {
+    let pubkey = commitment.verify_signature()?;
+    let RngCommitment {
+        commitment,
+        timestamp,
+        stream,
+    } = commitment.message.as_inner();
+    let timestamp = GuessTimestamp::from(*timestamp);
+    check_rng_stream(ctx.app_state(), stream.as_deref())?;
+    check_rng_signer(ctx.app_state(), timestamp, pubkey)?;
+    let current = GuessTimestamp::after(ctx.block_time(), ctx.app_state().round_period);
+    anyhow::ensure!(
+        timestamp > current,
+        "Commitment for round {timestamp} arrived after betting opened"
+    );
+
+    // Forget about commitments for past rounds nobody bet on.
+    let state = ctx.app_state_mut();
+    let stale = state
+        .commitments
+        .iter()
+        .map(|(round, _)| *round)
+        .take_while(|round| *round < current)
+        .filter(|round| state.pending_wagers.get(round).is_none())
+        .collect::<Vec<_>>();
+    for round in stale {
+        state.commitments.remove(&round);
+    }
+
+    match state.commitments.get(&timestamp) {
+        Some(old) => anyhow::ensure!(
+            old == commitment,
+            "Conflicting commitments for round {timestamp}: {old} and {commitment}"
+        ),
+        None => {
+            state.commitments.insert(timestamp, commitment.clone());
+        }
+    }
+    Ok(())
+}
+
+fn set_rng_keys(
+    ctx: &mut ExecutionContext<'_, GuessGame>,
+    key_set: &SignedTaggedJson<RngKeySet>,
+) -> Result<()> {
+    let signer = key_set.verify_signature()?;
+    let issued = GuessTimestamp::from(key_set.message.as_inner().issued);
+    anyhow::ensure!(
+        issued <= GuessTimestamp::after(ctx.block_time(), ctx.app_state().round_period),
+        "RNG key set issued in the future round {issued}"
+    );
+    if let Some(last) = ctx.app_state().rng_keys_issued {
+        anyhow::ensure!(
+            issued > last,
+            "RNG key set from round {issued} is not newer than the current one from round {last}"
+        );
+    }
+    check_rng_signer(ctx.app_state(), issued, signer)?;
+
+    let mut keys = RngKey::from_key_set(key_set.message.as_inner())?;
+    keys.sort_by_key(|key| key.valid_from);
+    for pair in keys.windows(2) {
+        let [prev, next] = pair else { unreachable!() };
+        anyhow::ensure!(
+            prev.valid_until
+                .zip(next.valid_from)
+                .is_some_and(|(until, from)| until <= from),
+            "RNG keys {} and {} have overlapping validity windows",
+            prev.public_key,
+            next.public_key
+        );
+    }
+    // Every round with bets still to settle needs a key to settle it with,
+    // and so do the round taking bets and upcoming ones with a commitment.
+    let open = GuessTimestamp::after(ctx.block_time(), ctx.app_state().round_period);
+    let state = ctx.app_state();
+    let needed = state
+        .pending_wagers
+        .iter()
+        .map(|(round, _)| *round)
+        .chain(
+            state
+                .commitments
+                .iter()
+                .map(|(round, _)| *round)
+                .filter(|round| *round >= open),
+        )
+        .chain([open]);
+    for round in needed {
+        anyhow::ensure!(
+            keys.iter().any(|key| key.is_valid_at(round)),
+            "RNG key set has no key valid for round {round}, which could then never be settled"
+        );
+    }
+
+    let mut rng_keys = MerkleVec::new();
+    for key in keys {
+        rng_keys.push(key);
+    }
+    let state = ctx.app_state_mut();
+    state.rng_keys = rng_keys;
+    state.rng_keys_issued = Some(issued);
+    Ok(())
+}
+
+/// Check that a revealed number matches the commitment made for its round.
+///
+/// The commitment is the SHA-256 of the round's seed, and the number
+/// is derived from the seed over the full u32 range.
+fn check_reveal(commitment: &str, number: u32, seed: Option<&str>) -> Result<()> {
+    let seed = hex::decode(seed.context("RNG result is missing its seed")?)?;
+    let actual = hex::encode(Sha256::digest(&seed));
+    anyhow::ensure!(
+        actual == commitment,
+        "Revealed seed does not match commitment: expected {commitment}, got {actual}"
+    );
+    let seed =
+        <[u8; 32]>::try_from(seed).map_err(|_| anyhow::anyhow!("RNG seed is not 32 bytes"))?;
+    let seed_number = derive_number(&seed, None);
+    anyhow::ensure!(
+        seed_number == number,
+        "Revealed number {number} does not match seed"
+    );
+    Ok(())
+}
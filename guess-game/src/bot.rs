@@ -1,22 +1,52 @@
-use anyhow::Result;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
 use kolme::*;
+use rng_protocol::MAX_RANGE;
+use tokio::sync::watch;
 
 use crate::{
     app::{GuessGame, GuessMessage},
     rng_server::RngServer,
+    state::RngKey,
+    time::GuessTimestamp,
 };
 
 pub async fn bot(kolme: Kolme<GuessGame>, rng_server: RngServer) -> Result<()> {
     let secret = SecretKey::random();
+    let rng_server = Arc::new(rng_server);
+    // Latest round pushed by the RNG server, so we can settle the moment it starts.
+    let (latest, mut pushed) = watch::channel(None);
+    tokio::spawn({
+        let rng_server = rng_server.clone();
+        async move { rng_server.watch_rounds(latest).await }
+    });
+    // Round in which we last synced the RNG key set, so we only check once per round.
+    let mut keys_synced = None;
     loop {
-        // Better than just sleeping 1 second would be to do proper scheduling.
-        // Not doing that because the logic is semi-complicated and not important
-        // for implementing the core demo.
-        if let Err(e) = bot_once(&kolme, &rng_server, &secret).await {
+        let round_period = kolme.read().get_app_state().round_period;
+        let round = GuessTimestamp::after(Timestamp::now(), round_period);
+        if keys_synced != Some(round) {
+            match keys_once(&kolme, &rng_server, &secret).await {
+                Ok(()) => keys_synced = Some(round),
+                Err(e) => println!("Error syncing RNG keys: {e}"),
+            }
+        }
+        if let Err(e) = commit_once(&kolme, &rng_server, &secret).await {
+            println!("Error recording commitment: {e}");
+        }
+        let latest = *pushed.borrow_and_update();
+        if let Err(e) = bot_once(&kolme, &rng_server, &secret, latest).await {
             println!("Error settling: {e}");
         }
 
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+        // Wake up as soon as the RNG server pushes a new round. Keep polling
+        // every second as well, which covers the time the stream is down and
+        // the key and commitment checks above.
+        tokio::select! {
+            Ok(()) = pushed.changed() => (),
+            () = tokio::time::sleep(tokio::time::Duration::from_secs(1)) => (),
+        }
     }
 }
 
@@ -24,21 +54,138 @@ async fn bot_once(
     kolme: &Kolme<GuessGame>,
     rng_server: &RngServer,
     secret: &SecretKey,
+    latest: Option<GuessTimestamp>,
 ) -> Result<()> {
-    let kolme_r = kolme.read();
-    let Some((guess_timestamp, _wagers)) = kolme_r.get_app_state().pending_wagers.iter().next()
-    else {
-        return Ok(());
+    let (due, beacon, threshold) = {
+        let kolme_r = kolme.read();
+        let state = kolme_r.get_app_state();
+        let now = Timestamp::now();
+        let due = state
+            .pending_wagers
+            .iter()
+            .map(|(guess_timestamp, _wagers)| *guess_timestamp)
+            // A pushed round has started even if our clock is slightly behind.
+            .take_while(|guess_timestamp| {
+                guess_timestamp.to_timestamp(state.round_period) <= now
+                    || latest.is_some_and(|latest| *guess_timestamp <= latest)
+            })
+            .collect::<Vec<_>>();
+        (
+            due,
+            state.rng_beacon,
+            state.rng_quorum.as_ref().map(|quorum| quorum.threshold),
+        )
     };
-    let timestamp = Timestamp::from(guess_timestamp);
-    if timestamp > Timestamp::now() {
+    if let Some(threshold) = threshold {
+        for guess_timestamp in due {
+            let shares = rng_server.get_shares(guess_timestamp, threshold).await?;
+            kolme
+                .sign_propose_await_transaction(
+                    secret,
+                    vec![Message::App(GuessMessage::SettleBetShares { shares })],
+                )
+                .await?;
+        }
         return Ok(());
     }
-    let result = rng_server.get_result(*guess_timestamp).await?;
+    let Some(first) = due.first().copied() else {
+        return Ok(());
+    };
+    // Fetch everything we need in one request. After a long outage this
+    // may not cover every pending round, the rest get picked up next time.
+    let due = due
+        .into_iter()
+        .filter(|guess_timestamp| guess_timestamp.rounds_since(first) < MAX_RANGE)
+        .collect::<Vec<_>>();
+    let last = *due
+        .last()
+        .context("Impossible: first due round is always in range")?;
+    let mut results = rng_server.get_results(first, last).await?;
+    // In beacon mode, settling a round also needs the round before it,
+    // unless it's the beacon's genesis round.
+    if beacon {
+        let linked = results
+            .get(&first)
+            .is_some_and(|result| result.message.as_inner().previous_signature_hash.is_some());
+        if let Some(previous) = first.previous().filter(|_| linked) {
+            results.append(&mut rng_server.get_results(previous, previous).await?);
+        }
+    }
+    let get = |guess_timestamp: GuessTimestamp| {
+        results
+            .get(&guess_timestamp)
+            .cloned()
+            .with_context(|| format!("RNG server did not return round {guess_timestamp}"))
+    };
+
+    for guess_timestamp in due {
+        let result = get(guess_timestamp)?;
+        let linked = result.message.as_inner().previous_signature_hash.is_some();
+        let previous = match guess_timestamp.previous() {
+            Some(previous) if beacon && linked => Some(get(previous)?),
+            _ => None,
+        };
+        kolme
+            .sign_propose_await_transaction(
+                secret,
+                vec![Message::App(GuessMessage::SettleBet { result, previous })],
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Make sure the RNG server's commitment for the next round is on chain before betting opens.
+async fn commit_once(
+    kolme: &Kolme<GuessGame>,
+    rng_server: &RngServer,
+    secret: &SecretKey,
+) -> Result<()> {
+    let next_round = {
+        let kolme_r = kolme.read();
+        let state = kolme_r.get_app_state();
+        let next_round = GuessTimestamp::after(Timestamp::now(), state.round_period).next();
+        if !state.rng_commitments || state.commitments.get(&next_round).is_some() {
+            return Ok(());
+        }
+        next_round
+    };
+    let commitment = rng_server.get_commitment(next_round).await?;
+    kolme
+        .sign_propose_await_transaction(
+            secret,
+            vec![Message::App(GuessMessage::RecordCommitment { commitment })],
+        )
+        .await?;
+    Ok(())
+}
+
+/// Record the RNG server's key set on chain if it changed.
+async fn keys_once(
+    kolme: &Kolme<GuessGame>,
+    rng_server: &RngServer,
+    secret: &SecretKey,
+) -> Result<()> {
+    let keys = rng_server.get_key_set().await?;
+    let mut new_keys = RngKey::from_key_set(keys.message.as_inner())?;
+    new_keys.sort_by_key(|key| key.valid_from);
+    {
+        let kolme_r = kolme.read();
+        let mut old_keys = kolme_r
+            .get_app_state()
+            .rng_keys
+            .iter()
+            .cloned()
+            .collect::<Vec<_>>();
+        old_keys.sort_by_key(|key| key.valid_from);
+        if old_keys == new_keys {
+            return Ok(());
+        }
+    }
     kolme
         .sign_propose_await_transaction(
             secret,
-            vec![Message::App(GuessMessage::SettleBet { result })],
+            vec![Message::App(GuessMessage::SetRngKeys { keys })],
         )
         .await?;
     Ok(())
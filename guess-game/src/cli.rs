@@ -1,8 +1,10 @@
 use std::{net::SocketAddr, path::PathBuf};
 
-use kolme::{PublicKey, SecretKey};
+use kolme::{Decimal, PublicKey, SecretKey};
 use reqwest::Url;
 
+use crate::{rng_server::RngShareServer, time::RoundPeriod};
+
 #[derive(clap::Parser)]
 pub struct Opt {
     /// Root URL for the RNG server
@@ -17,12 +19,88 @@ pub struct Opt {
     /// We specify this and then verify it against the server to ensure
     /// we don't get poisoned by a fake server. There's no real money
     /// in this game, but that doesn't mean we should be lax with security!
+    ///
+    /// This is the genesis key. Rotated keys are picked up from the
+    /// server's signed key set and recorded on chain.
     #[clap(
         long,
         env = "RNG_PUBLIC_KEY",
         default_value = "0294c4243aa4452127fa8a13a18c54ace42df8da14637e9c80dccf953a27b9f917"
     )]
     pub rng_public_key: PublicKey,
+    /// Require RNG results to be hash-chained to the previous round.
+    ///
+    /// Only enable this when the RNG server runs in beacon mode.
+    #[clap(long, env = "RNG_BEACON")]
+    pub rng_beacon: bool,
+    /// Require the RNG server to commit to each round's result before betting opens.
+    ///
+    /// Bets are only accepted for rounds with a commitment on chain, and
+    /// settlement checks the revealed number against that commitment.
+    #[clap(long, env = "RNG_COMMITMENTS")]
+    pub rng_commitments: bool,
+    /// Require RNG results to carry an ECVRF proof under the RNG public key.
+    ///
+    /// Only enable this when the RNG server runs in VRF mode. The proof
+    /// shows the number was derived honestly from the round's timestamp.
+    #[clap(long, env = "RNG_VRF")]
+    pub rng_vrf: bool,
+    /// Independent RNG operators for threshold mode, as `PUBLIC_KEY@URL`.
+    ///
+    /// Rounds are then settled from the signed shares of at least
+    /// `--rng-threshold` of these operators instead of a single server's
+    /// result. Any that many shares give the same number. The keys must
+    /// come from `rng-server generate --shares`, listed in the order dealt.
+    #[clap(
+        long = "rng-share-server",
+        env = "RNG_SHARE_SERVERS",
+        value_delimiter = ',',
+        requires = "rng_threshold",
+        conflicts_with_all = ["rng_beacon", "rng_commitments", "rng_vrf"]
+    )]
+    pub rng_share_servers: Vec<RngShareServer>,
+    /// Number of operator shares needed to settle a round in threshold mode.
+    #[clap(long, env = "RNG_THRESHOLD", requires = "rng_share_servers")]
+    pub rng_threshold: Option<u8>,
+    /// Named stream on the RNG server to take rounds from.
+    ///
+    /// Games sharing an RNG server should each use their own stream, so
+    /// they get independent numbers and results for one can't be replayed
+    /// into another. The RNG server only chains its default stream, so
+    /// this can't be combined with beacon mode.
+    #[clap(long, env = "RNG_STREAM", conflicts_with = "rng_beacon")]
+    pub rng_stream: Option<String>,
+    /// Length of each round of betting, such as 15s, 5m or 1h.
+    ///
+    /// This is fixed at genesis and must match the RNG server's period.
+    #[clap(long, env = "ROUND_PERIOD", default_value = "1m")]
+    pub round_period: RoundPeriod,
+    /// House cut of each settled pot, in basis points (1/100th of a percent).
+    ///
+    /// Minted to `--treasury-account`. Fixed at genesis.
+    #[clap(long, env = "RAKE_BPS", requires = "treasury_account")]
+    pub rake_bps: Option<u16>,
+    /// Account ID receiving the rake. It must already exist when the first pot is settled.
+    #[clap(long, env = "TREASURY_ACCOUNT", requires = "rake_bps")]
+    pub treasury_account: Option<u64>,
+    /// Share of each settled pot going to the jackpot, in basis points.
+    ///
+    /// The jackpot carries over between rounds, and is paid out to the
+    /// guesses hitting the number exactly. Fixed at genesis.
+    #[clap(long, env = "JACKPOT_BPS")]
+    pub jackpot_bps: Option<u16>,
+    /// Smallest amount for a single bet. Bets must always be positive.
+    #[clap(long, env = "MIN_BET")]
+    pub min_bet: Option<Decimal>,
+    /// Largest amount for a single bet.
+    #[clap(long, env = "MAX_BET")]
+    pub max_bet: Option<Decimal>,
+    /// Most an account may stake on a single round, over all its bets.
+    #[clap(long, env = "MAX_ROUND_STAKE")]
+    pub max_round_stake: Option<Decimal>,
+    /// Most bets an account may place on a single round.
+    #[clap(long, env = "MAX_ROUND_WAGERS")]
+    pub max_round_wagers: Option<u32>,
     /// Secret key used for the validators.
     ///
     /// Since this application includes no external chains, we use the same
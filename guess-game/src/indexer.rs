@@ -20,12 +20,16 @@ pub struct IndexerState {
     pub total_winnings: HashMap<AccountId, Decimal>,
     pub user_bet_history: HashMap<AccountId, BTreeMap<GuessTimestamp, BTreeMap<u8, Decimal>>>,
     pub results: BTreeMap<GuessTimestamp, RoundResults>,
+    /// Lifetime rake taken by the house.
+    pub house_revenue: Decimal,
 }
 
 #[derive(serde::Serialize, Debug, Clone, PartialEq, Eq)]
 pub struct RoundResults {
     pub number: u8,
     pub winnings: BTreeMap<AccountId, Decimal>,
+    /// Shares of the jackpot, if any guess hit the number exactly.
+    pub jackpot: BTreeMap<AccountId, Decimal>,
 }
 
 #[derive(serde::Serialize, Clone, Copy)]
@@ -94,12 +98,50 @@ fn update(state: &mut IndexerState, logs: &[Vec<String>]) {
                         .entry(guess)
                         .or_default() += amount;
                 }
+                GuessGameLog::Wagers {
+                    account,
+                    timestamp,
+                    bets,
+                } => {
+                    let round = state
+                        .user_bet_history
+                        .entry(account)
+                        .or_default()
+                        .entry(timestamp)
+                        .or_default();
+                    for (guess, amount) in bets {
+                        *round.entry(guess).or_default() += amount;
+                    }
+                }
+                GuessGameLog::WagerCancelled {
+                    account,
+                    timestamp,
+                    guess,
+                    amount,
+                } => {
+                    let Some(history) = state.user_bet_history.get_mut(&account) else {
+                        continue;
+                    };
+                    let Some(round) = history.get_mut(&timestamp) else {
+                        continue;
+                    };
+                    if let Some(bet) = round.get_mut(&guess) {
+                        *bet -= amount;
+                        if bet.is_zero() {
+                            round.remove(&guess);
+                        }
+                    }
+                    if round.is_empty() {
+                        history.remove(&timestamp);
+                    }
+                }
                 GuessGameLog::NewWinner { finished, number } => {
                     let old = state.results.insert(
                         finished,
                         RoundResults {
                             number,
                             winnings: BTreeMap::new(),
+                            jackpot: BTreeMap::new(),
                         },
                     );
                     assert_eq!(old, None);
@@ -118,6 +160,23 @@ fn update(state: &mut IndexerState, logs: &[Vec<String>]) {
                         .insert(winner, amount);
                     assert_eq!(old, None);
                 }
+                GuessGameLog::Jackpot {
+                    winner,
+                    amount,
+                    finished,
+                } => {
+                    *state.total_winnings.entry(winner).or_default() += amount;
+                    let old = state
+                        .results
+                        .get_mut(&finished)
+                        .expect("Logic error: NewWinner must come before Jackpot")
+                        .jackpot
+                        .insert(winner, amount);
+                    assert_eq!(old, None);
+                }
+                GuessGameLog::Rake { amount, .. } => {
+                    state.house_revenue += amount;
+                }
             }
         }
     }
@@ -0,0 +1,124 @@
+use std::fmt::Display;
+
+use kolme::*;
+
+/// Limits on bets, fixed at genesis. A missing limit isn't enforced.
+#[derive(Debug, Clone)]
+pub struct BetLimits {
+    /// Smallest amount for a single bet.
+    pub min_bet: Option<Decimal>,
+    /// Largest amount for a single bet.
+    pub max_bet: Option<Decimal>,
+    /// Most an account may stake on a single round, over all its bets.
+    pub max_round_stake: Option<Decimal>,
+    /// Most bets an account may place on a single round.
+    ///
+    /// Also keeps the list of a round's wagers from growing without bound.
+    pub max_round_wagers: Option<u32>,
+}
+
+/// Why a bet was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BetError {
+    NotPositive { amount: Decimal },
+    BelowMinimum { amount: Decimal, min: Decimal },
+    AboveMaximum { amount: Decimal, max: Decimal },
+    RoundStakeExceeded { stake: Decimal, max: Decimal },
+    TooManyWagers { max: u32 },
+}
+
+impl Display for BetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BetError::NotPositive { amount } => write!(f, "Bet of {amount} is not positive"),
+            BetError::BelowMinimum { amount, min } => {
+                write!(f, "Bet of {amount} is below the minimum of {min}")
+            }
+            BetError::AboveMaximum { amount, max } => {
+                write!(f, "Bet of {amount} is above the maximum of {max}")
+            }
+            BetError::RoundStakeExceeded { stake, max } => write!(
+                f,
+                "Total stake of {stake} on this round is above the maximum of {max}"
+            ),
+            BetError::TooManyWagers { max } => {
+                write!(f, "Already placed the maximum of {max} bets on this round")
+            }
+        }
+    }
+}
+
+impl std::error::Error for BetError {}
+
+impl BetLimits {
+    /// Check the limits don't contradict each other.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(min) = self.min_bet {
+            anyhow::ensure!(min > Decimal::ZERO, "Minimum bet {min} must be positive");
+        }
+        if let (Some(min), Some(max)) = (self.min_bet, self.max_bet) {
+            anyhow::ensure!(min <= max, "Minimum bet {min} is above the maximum {max}");
+        }
+        if let (Some(max), Some(max_stake)) = (self.max_bet, self.max_round_stake) {
+            anyhow::ensure!(
+                max <= max_stake,
+                "Maximum bet {max} is above the maximum stake per round {max_stake}"
+            );
+        }
+        Ok(())
+    }
+
+    /// Check a new bet, given the account's bets already placed on the round.
+    pub fn check(&self, amount: Decimal, stake: Decimal, wagers: usize) -> Result<(), BetError> {
+        if amount <= Decimal::ZERO {
+            return Err(BetError::NotPositive { amount });
+        }
+        if let Some(min) = self.min_bet.filter(|min| amount < *min) {
+            return Err(BetError::BelowMinimum { amount, min });
+        }
+        if let Some(max) = self.max_bet.filter(|max| amount > *max) {
+            return Err(BetError::AboveMaximum { amount, max });
+        }
+        let stake = stake + amount;
+        if let Some(max) = self.max_round_stake.filter(|max| stake > *max) {
+            return Err(BetError::RoundStakeExceeded { stake, max });
+        }
+        if let Some(max) = self
+            .max_round_wagers
+            .filter(|max| wagers >= usize::try_from(*max).unwrap_or(usize::MAX))
+        {
+            return Err(BetError::TooManyWagers { max });
+        }
+        Ok(())
+    }
+}
+
+impl MerkleSerialize for BetLimits {
+    fn merkle_serialize(&self, serializer: &mut MerkleSerializer) -> Result<(), MerkleSerialError> {
+        let Self {
+            min_bet,
+            max_bet,
+            max_round_stake,
+            max_round_wagers,
+        } = self;
+        serializer.store(min_bet)?;
+        serializer.store(max_bet)?;
+        serializer.store(max_round_stake)?;
+        serializer.store(max_round_wagers)?;
+        Ok(())
+    }
+}
+
+impl MerkleDeserialize for BetLimits {
+    fn merkle_deserialize(
+        deserializer: &mut MerkleDeserializer,
+        _version: usize,
+    ) -> Result<Self, MerkleSerialError> {
+        Ok(Self {
+            min_bet: deserializer.load()?,
+            max_bet: deserializer.load()?,
+            max_round_stake: deserializer.load()?,
+            max_round_wagers: deserializer.load()?,
+        })
+    }
+}
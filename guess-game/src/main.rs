@@ -3,18 +3,21 @@ mod app;
 mod bot;
 mod cli;
 mod indexer;
+mod limits;
 mod rng_server;
 mod state;
 mod time;
 
 use anyhow::{Context, Result};
 use api::make_api_server;
-use app::GuessGame;
+use app::{GameSettings, GuessGame, RngSettings};
 use bot::bot;
 use clap::Parser;
 use cli::Opt;
 use indexer::Indexer;
 use kolme::*;
+use limits::BetLimits;
+use state::{Rake, RngQuorum};
 use tokio::task::JoinSet;
 
 #[tokio::main]
@@ -26,18 +29,74 @@ async fn serve(opt: Opt) -> Result<()> {
     let Opt {
         rng_server_url,
         rng_public_key,
+        rng_beacon,
+        rng_commitments,
+        rng_vrf,
+        rng_share_servers,
+        rng_threshold,
+        rng_stream,
+        round_period,
+        rake_bps,
+        treasury_account,
+        jackpot_bps,
+        min_bet,
+        max_bet,
+        max_round_stake,
+        max_round_wagers,
         validator_secret_key,
         fjall_dir,
         postgres,
         bind,
     } = opt;
 
+    // In threshold mode, rounds are settled from the shares of several
+    // independent RNG operators rather than a single server.
+    let rng_quorum = rng_threshold
+        .map(|threshold| {
+            let keys = rng_share_servers
+                .iter()
+                .map(|server| server.public_key)
+                .collect();
+            RngQuorum::new(keys, threshold)
+        })
+        .transpose()?;
+
+    let rake = rake_bps
+        .zip(treasury_account)
+        .map(|(bps, treasury)| Rake::new(bps, AccountId(treasury)))
+        .transpose()?;
+    let settings = GameSettings::new(
+        rake,
+        jackpot_bps,
+        BetLimits {
+            min_bet,
+            max_bet,
+            max_round_stake,
+            max_round_wagers,
+        },
+    )?;
+
     // Initialize the RngServer value which will be used for looking up
     // random number results.
-    let rng_server = rng_server::RngServer::new(&rng_server_url, rng_public_key).await?;
+    let rng_server = rng_server::RngServer::new(&rng_server_url, rng_public_key, round_period)
+        .await?
+        .with_share_servers(rng_share_servers)
+        .with_stream(rng_stream.clone());
 
     // Initialize the GuessGame value, the core of any Kolme application.
-    let game = GuessGame::new(validator_secret_key.public_key(), rng_public_key);
+    let game = GuessGame::new(
+        validator_secret_key.public_key(),
+        RngSettings {
+            public_key: rng_public_key,
+            beacon: rng_beacon,
+            commitments: rng_commitments,
+            vrf: rng_vrf,
+            quorum: rng_quorum,
+            stream: rng_stream,
+            round_period,
+        },
+        settings,
+    );
 
     // Initialize the storage layer used by Kolme. For local testing, we stick
     // to Fjall for simplicity. Our deployed server uses PostgreSQL, which allows
@@ -1,87 +1,468 @@
-use anyhow::Result;
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::RwLock,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use k256::ecdsa::VerifyingKey;
 use kolme::*;
 use reqwest::Url;
+use rng_protocol::{
+    MAX_RANGE, RngCommitment, RngKeySet, RngReadiness, RngResult, RngShare, Signed,
+};
+use tokio::sync::watch;
+
+use crate::{
+    state::rng_verifying_key,
+    time::{GuessTimestamp, RoundPeriod},
+};
+
+/// How long to wait before reconnecting to the push stream.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Reconnect if the push stream is silent for this long.
+///
+/// The server sends a keep-alive comment every 15 seconds even without new rounds.
+const STREAM_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long to wait for each operator's share before moving on to the next.
+const SHARE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest `Retry-After` we wait out for a round that hasn't started yet.
+///
+/// Longer waits mean the clocks are far apart, so we give up and try
+/// again on the bot's next pass instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(10);
 
-use crate::time::GuessTimestamp;
+/// Warn if the RNG server's clock is further than this from ours.
+///
+/// We ask for each round as soon as it starts, so a server running
+/// behind makes us wait out `round_not_ready` responses, see
+/// [MAX_RETRY_AFTER]. One running ahead serves rounds early.
+const MAX_CLOCK_DRIFT: Duration = Duration::from_secs(2);
 
 pub struct RngServer {
     client: reqwest::Client,
     rng_server_url: Url,
-    rng_public_key: PublicKey,
+    /// Keys from the server's latest key set, used to sanity check responses.
+    known_keys: RwLock<Vec<VerifyingKey>>,
+    /// Operators to collect shares from in threshold mode.
+    share_servers: Vec<RngShareServer>,
+    /// Named stream to take rounds from, [None] for the default stream.
+    stream: Option<String>,
+}
+
+/// An independent RNG operator contributing shares in threshold mode.
+#[derive(Clone, Debug)]
+pub struct RngShareServer {
+    pub public_key: PublicKey,
+    pub url: Url,
+}
+
+/// Parses `PUBLIC_KEY@URL`.
+impl FromStr for RngShareServer {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (public_key, url) = s
+            .split_once('@')
+            .with_context(|| format!("Invalid RNG share server {s}, expected PUBLIC_KEY@URL"))?;
+        Ok(RngShareServer {
+            public_key: public_key.parse()?,
+            url: url.parse()?,
+        })
+    }
 }
 
 impl RngServer {
-    pub async fn new(rng_server_url: &Url, rng_public_key: PublicKey) -> Result<Self> {
-        let client = reqwest::Client::new();
-        keycheck(&client, rng_server_url, rng_public_key).await?;
-        Ok(RngServer {
-            client,
+    pub async fn new(
+        rng_server_url: &Url,
+        rng_public_key: PublicKey,
+        round_period: RoundPeriod,
+    ) -> Result<Self> {
+        let rng_server = RngServer {
+            client: reqwest::Client::new(),
             rng_server_url: rng_server_url.clone(),
-            rng_public_key,
-        })
+            known_keys: RwLock::new(vec![rng_verifying_key(&rng_public_key)?]),
+            share_servers: vec![],
+            stream: None,
+        };
+        rng_server.keycheck(rng_public_key).await?;
+        rng_server.period_check(round_period).await?;
+        Ok(rng_server)
+    }
+
+    /// Set the operators to collect shares from in threshold mode.
+    pub(crate) fn with_share_servers(self, share_servers: Vec<RngShareServer>) -> Self {
+        RngServer {
+            share_servers,
+            ..self
+        }
     }
 
-    pub(crate) async fn get_result(
+    /// Take rounds from the given named stream instead of the server's default one.
+    pub(crate) fn with_stream(self, stream: Option<String>) -> Self {
+        RngServer { stream, ..self }
+    }
+
+    /// Collect signed shares of a round from the operators, in the order they were configured.
+    ///
+    /// Stops once `threshold` operators have contributed. Operators that
+    /// are down or send a bad share are skipped.
+    pub(crate) async fn get_shares(
         &self,
         guess_timestamp: GuessTimestamp,
-    ) -> Result<SignedTaggedJson<RngResult>> {
-        #[derive(serde::Deserialize)]
-        struct NumberRes {
-            signature: Signature,
-            serialized: String,
-            recovery_id: RecoveryId,
+        threshold: u8,
+    ) -> Result<Vec<SignedTaggedJson<RngShare>>> {
+        let mut shares = vec![];
+        for server in &self.share_servers {
+            if shares.len() >= usize::from(threshold) {
+                break;
+            }
+            match self.get_share(server, guess_timestamp).await {
+                Ok(share) => shares.push(share),
+                Err(e) => println!(
+                    "Unable to get round {guess_timestamp} share from {}: {e}",
+                    server.url
+                ),
+            }
         }
-        let NumberRes {
-            signature,
-            serialized,
-            recovery_id,
-        } = self
-            .client
-            .get(
-                self.rng_server_url
-                    .join("number/")?
-                    .join(&guess_timestamp.to_string())?,
+        anyhow::ensure!(
+            shares.len() >= usize::from(threshold),
+            "Only {} of {threshold} RNG shares available for round {guess_timestamp}",
+            shares.len()
+        );
+        Ok(shares)
+    }
+
+    async fn get_share(
+        &self,
+        server: &RngShareServer,
+        guess_timestamp: GuessTimestamp,
+    ) -> Result<SignedTaggedJson<RngShare>> {
+        let share: Signed<RngShare> = self
+            .send_round(
+                self.client
+                    .get(
+                        self.stream_url(&server.url, "share/")?
+                            .join(&guess_timestamp.to_string())?,
+                    )
+                    .timeout(SHARE_TIMEOUT),
             )
+            .await?
+            .json()
+            .await?;
+        share.payload()?;
+        anyhow::ensure!(
+            share.signer()? == rng_verifying_key(&server.public_key)?,
+            "Share not signed by {}",
+            server.public_key
+        );
+        signed_tagged_json(share)
+    }
+
+    /// Get the results for all rounds from `from` to `to`, inclusive, in a single request.
+    ///
+    /// The range may cover at most [MAX_RANGE] rounds.
+    pub(crate) async fn get_results(
+        &self,
+        from: GuessTimestamp,
+        to: GuessTimestamp,
+    ) -> Result<BTreeMap<GuessTimestamp, SignedTaggedJson<RngResult>>> {
+        let mut url = self.stream_url(&self.rng_server_url, "numbers")?;
+        url.query_pairs_mut()
+            .append_pair("from", &from.to_string())
+            .append_pair("to", &to.to_string());
+        let responses: Vec<Signed<RngResult>> =
+            self.send_round(self.client.get(url)).await?.json().await?;
+        responses
+            .into_iter()
+            .map(|signed| {
+                let timestamp = GuessTimestamp::from(self.check_result(&signed)?.timestamp);
+                Ok((timestamp, signed_tagged_json(signed)?))
+            })
+            .collect()
+    }
+
+    /// Follow the server's push stream, publishing each round to `latest` as soon as it starts.
+    ///
+    /// The push stream always carries the default stream's results, but
+    /// only the round numbers are used so that works for named streams too.
+    /// Every pushed result is checked like any other response before its
+    /// round is published. Reconnects whenever the stream drops, and only
+    /// returns once nobody is watching `latest` anymore.
+    pub(crate) async fn watch_rounds(&self, latest: watch::Sender<Option<GuessTimestamp>>) {
+        while !latest.is_closed() {
+            if let Err(e) = self.stream_rounds(&latest).await {
+                println!("RNG server stream dropped, reconnecting: {e}");
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    }
+
+    async fn stream_rounds(&self, latest: &watch::Sender<Option<GuessTimestamp>>) -> Result<()> {
+        let mut res = self
+            .client
+            .get(self.rng_server_url.join("stream")?)
             .send()
             .await?
-            .error_for_status()?
+            .error_for_status()?;
+        let mut buffer = vec![];
+        loop {
+            let chunk = tokio::time::timeout(STREAM_TIMEOUT, res.chunk())
+                .await
+                .context("No events from RNG server stream")??
+                .context("RNG server closed the stream")?;
+            buffer.extend_from_slice(&chunk);
+            // Events are separated by a blank line.
+            while let Some(end) = buffer.windows(2).position(|window| window == b"\n\n") {
+                let event = String::from_utf8(buffer.drain(..end + 2).collect())?;
+                // Only data lines matter, the rest are event names, IDs and keep-alive comments.
+                let data = event
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("data:"))
+                    .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                if data.is_empty() {
+                    continue;
+                }
+                let round = GuessTimestamp::from(
+                    self.check_result(&serde_json::from_str(&data)?)?.timestamp,
+                );
+                latest.send_if_modified(|latest| {
+                    let newer = latest.is_none_or(|seen| seen < round);
+                    if newer {
+                        *latest = Some(round);
+                    }
+                    newer
+                });
+            }
+        }
+    }
+
+    pub(crate) async fn get_commitment(
+        &self,
+        guess_timestamp: GuessTimestamp,
+    ) -> Result<SignedTaggedJson<RngCommitment>> {
+        self.get_signed(self.round_url("commitment/", guess_timestamp)?)
+            .await
+    }
+
+    /// Get the server's current key set.
+    ///
+    /// The key set must be signed by one of its own keys. Its keys are
+    /// then used for checking later responses.
+    pub(crate) async fn get_key_set(&self) -> Result<SignedTaggedJson<RngKeySet>> {
+        let key_set: Signed<RngKeySet> = self
+            .fetch_signed(self.rng_server_url.join("public-keys")?)
+            .await?;
+        let keys = key_set
+            .payload()?
+            .keys
+            .iter()
+            .map(|key| {
+                Ok(VerifyingKey::from_sec1_bytes(&hex::decode(
+                    &key.public_key,
+                )?)?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        anyhow::ensure!(
+            keys.contains(&key_set.signer()?),
+            "RNG key set not signed by any key in the set"
+        );
+        *self
+            .known_keys
+            .write()
+            .map_err(|_| anyhow::anyhow!("Known keys lock poisoned"))? = keys;
+        signed_tagged_json(key_set)
+    }
+
+    fn round_url(&self, path: &str, guess_timestamp: GuessTimestamp) -> Result<Url> {
+        Ok(self
+            .stream_url(&self.rng_server_url, path)?
+            .join(&guess_timestamp.to_string())?)
+    }
+
+    /// URL of a per-stream endpoint, under `stream/{name}/` for named streams.
+    fn stream_url(&self, base: &Url, path: &str) -> Result<Url> {
+        Ok(match &self.stream {
+            None => base.join(path)?,
+            Some(name) => base.join(&format!("stream/{name}/"))?.join(path)?,
+        })
+    }
+
+    async fn get_signed<T>(&self, url: Url) -> Result<SignedTaggedJson<T>>
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned + PartialEq,
+    {
+        let signed = self.fetch_signed(url).await?;
+        signed.payload()?;
+        let signer = signed.signer()?;
+        anyhow::ensure!(
+            self.known_keys()?.contains(&signer),
+            "RNG response signed by unknown key {}",
+            hex::encode(signer.to_sec1_bytes())
+        );
+        signed_tagged_json(signed)
+    }
+
+    /// Check a result with [rng_protocol::verify] against the server's known keys.
+    ///
+    /// The seed is only checked in VRF mode, we don't hold the HMAC secret.
+    fn check_result(&self, signed: &Signed<RngResult>) -> Result<RngResult> {
+        Ok(rng_protocol::verify(signed, &self.known_keys()?, None)?.result)
+    }
+
+    fn known_keys(&self) -> Result<Vec<VerifyingKey>> {
+        Ok(self
+            .known_keys
+            .read()
+            .map_err(|_| anyhow::anyhow!("Known keys lock poisoned"))?
+            .clone())
+    }
+
+    async fn fetch_signed<T>(&self, url: Url) -> Result<Signed<T>>
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        Ok(self.send_round(self.client.get(url)).await?.json().await?)
+    }
+
+    /// Send a request for a round, waiting for it to start if needed.
+    ///
+    /// The server answers `425 Too Early` for rounds that haven't started
+    /// by its clock, which may be a little behind ours, along with a
+    /// `Retry-After` telling us when they do.
+    async fn send_round(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        loop {
+            let res = request
+                .try_clone()
+                .context("Unable to retry RNG server request")?
+                .send()
+                .await?;
+            if res.status() != reqwest::StatusCode::TOO_EARLY {
+                return Ok(res.error_for_status()?);
+            }
+            let retry_after = res
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .map(Duration::from_secs)
+                .context("RNG server round not ready, without a Retry-After")?;
+            anyhow::ensure!(
+                retry_after <= MAX_RETRY_AFTER,
+                "RNG server round not ready for another {}s",
+                retry_after.as_secs()
+            );
+            tokio::time::sleep(retry_after).await;
+        }
+    }
+
+    /// Check the RNG server passes its self-test, and compare its clock to ours.
+    async fn readiness_check(&self) -> Result<()> {
+        let sent = SystemTime::now();
+        let readiness: RngReadiness = self
+            .client
+            .get(self.rng_server_url.join("readyz")?)
+            .send()
+            .await?
+            .error_for_status()
+            .context("RNG server is not ready")?
             .json()
             .await?;
-        let signed = SignedTaggedJson::<RngResult> {
-            message: TaggedJson::try_from_string(serialized)?,
-            signature,
-            recovery_id,
-        };
-        anyhow::ensure!(signed.verify_signature()? == self.rng_public_key);
-        Ok(signed)
+        // The server read its clock somewhere during the request, assume halfway.
+        let elapsed = sent.elapsed().unwrap_or_default();
+        let ours = (sent + elapsed / 2).duration_since(UNIX_EPOCH)?.as_secs();
+        println!(
+            "RNG server {} is ready, signing with key {}",
+            readiness.version, readiness.fingerprint
+        );
+        let drift = readiness.server_time.abs_diff(ours);
+        if drift > MAX_CLOCK_DRIFT.as_secs() {
+            let direction = if readiness.server_time > ours {
+                "ahead of"
+            } else {
+                "behind"
+            };
+            println!("Warning: RNG server clock is {drift}s {direction} ours");
+        }
+        Ok(())
     }
-}
 
-/// Check that we have the correct public key/RNG server combo.
-async fn keycheck(
-    client: &reqwest::Client,
-    rng_server: &Url,
-    rng_public_key: PublicKey,
-) -> Result<()> {
-    let actual_public_key: PublicKey = client
-        .get(rng_server.join("public-key")?)
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await?;
-    if rng_public_key == actual_public_key {
-        println!("Confirmed RNG server is using expected public key {rng_public_key}");
+    /// Check that the RNG server's rounds line up with ours.
+    async fn period_check(&self, round_period: RoundPeriod) -> Result<()> {
+        let actual: u64 = self
+            .client
+            .get(self.rng_server_url.join("round-period")?)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        anyhow::ensure!(
+            actual == round_period.as_secs(),
+            "Round period mismatch: expected {round_period}, but RNG server uses {actual}s"
+        );
         Ok(())
-    } else {
-        Err(anyhow::anyhow!(
-            "Public key mismatch: expected {rng_public_key}, but got {actual_public_key}"
-        ))
     }
+
+    /// Check that we have the correct public key/RNG server combo, and that it's ready.
+    ///
+    /// After a key rotation the server may sign with a newer key, but the
+    /// expected key must still be part of its key set. Also warns if the
+    /// server's clock has drifted from ours.
+    async fn keycheck(&self, rng_public_key: PublicKey) -> Result<()> {
+        self.readiness_check().await?;
+        let key_set = self.get_key_set().await?;
+        let keys = &key_set.message.as_inner().keys;
+        if keys.iter().any(|key| {
+            key.public_key
+                .parse::<PublicKey>()
+                .is_ok_and(|key| key == rng_public_key)
+        }) {
+            println!("Confirmed RNG server is using expected public key {rng_public_key}");
+            Ok(())
+        } else {
+            let actual = keys
+                .iter()
+                .map(|key| key.public_key.as_str())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(anyhow::anyhow!(
+                "Public key mismatch: expected {rng_public_key}, but got {actual}"
+            ))
+        }
+    }
+}
+
+/// Carry an RNG server response on chain.
+///
+/// Both sign the same `serialized` payload, so the signature carries over as is.
+fn signed_tagged_json<T>(signed: Signed<T>) -> Result<SignedTaggedJson<T>>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let Signed {
+        signature,
+        recovery_id,
+        serialized,
+        ..
+    } = signed;
+    Ok(SignedTaggedJson {
+        message: TaggedJson::try_from_string(serialized)?,
+        signature: Signature::from_slice(&hex::decode(signature)?)
+            .context("Invalid RNG server signature")?,
+        recovery_id: RecoveryId::from_byte(recovery_id)
+            .context("Invalid RNG server recovery ID")?,
+    })
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq, Eq, Clone)]
-pub struct RngResult {
-    pub number: u32,
-    pub timestamp: i64,
+/// Hash of a signature as used for linking beacon rounds, hex encoded.
+pub fn signature_hash(signature: &Signature) -> Result<String> {
+    Ok(hex::encode(rng_protocol::signature_hash(&hex::encode(
+        signature.to_bytes(),
+    ))?))
 }
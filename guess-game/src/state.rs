@@ -1,12 +1,166 @@
 use kolme::*;
+use rng_protocol::RngKeySet;
 
-use crate::time::GuessTimestamp;
+use crate::{
+    limits::BetLimits,
+    time::{GuessTimestamp, RoundPeriod},
+};
 
 #[derive(Debug, Clone)]
 pub struct GuessState {
-    pub rng_public_key: PublicKey,
+    /// RNG server keys, at most one of them valid for any given round.
+    pub rng_keys: MerkleVec<RngKey>,
+    /// When the current RNG key set was issued, [None] for the genesis key.
+    pub rng_keys_issued: Option<GuessTimestamp>,
+    pub rng_beacon: bool,
+    pub rng_commitments: bool,
+    pub rng_vrf: bool,
+    /// Independent RNG operators whose shares settle rounds, if running in threshold mode.
+    pub rng_quorum: Option<RngQuorum>,
+    /// Named stream on the RNG server this game takes its rounds from.
+    pub rng_stream: Option<String>,
+    pub round_period: RoundPeriod,
+    /// House cut of each settled pot, if any.
+    pub rake: Option<Rake>,
+    /// Share of each settled pot set aside for the jackpot, in basis points.
+    pub jackpot_bps: Option<u16>,
+    /// Jackpot carried over between rounds, until a guess hits the number exactly.
+    pub jackpot: MerkleMap<AssetId, Decimal>,
+    pub bet_limits: BetLimits,
     pub received_funds: MerkleMap<AccountId, BlockHeight>,
     pub pending_wagers: MerkleMap<GuessTimestamp, MerkleVec<Wager>>,
+    /// Hex-encoded commitments from the RNG server for upcoming rounds.
+    pub commitments: MerkleMap<GuessTimestamp, String>,
+}
+
+/// An RNG server key along with the rounds it's valid for.
+///
+/// `valid_from` is inclusive, `valid_until` exclusive. A missing bound
+/// leaves the window open on that side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RngKey {
+    pub public_key: PublicKey,
+    pub valid_from: Option<GuessTimestamp>,
+    pub valid_until: Option<GuessTimestamp>,
+}
+
+impl RngKey {
+    /// Convert a key set from the RNG server to the keys we store on chain.
+    pub fn from_key_set(key_set: &RngKeySet) -> anyhow::Result<Vec<RngKey>> {
+        key_set
+            .keys
+            .iter()
+            .map(|key| {
+                Ok(RngKey {
+                    public_key: key.public_key.parse()?,
+                    valid_from: key.valid_from.map(GuessTimestamp::from),
+                    valid_until: key.valid_until.map(GuessTimestamp::from),
+                })
+            })
+            .collect()
+    }
+
+    pub fn is_valid_at(&self, timestamp: GuessTimestamp) -> bool {
+        self.valid_from.is_none_or(|from| from <= timestamp)
+            && self.valid_until.is_none_or(|until| timestamp < until)
+    }
+}
+
+/// An RNG server or operator key as the k256 key [rng_protocol] checks against.
+pub fn rng_verifying_key(key: &PublicKey) -> anyhow::Result<k256::ecdsa::VerifyingKey> {
+    let sec1 = hex::decode(key.to_string())?;
+    Ok(k256::ecdsa::VerifyingKey::from_sec1_bytes(&sec1)?)
+}
+
+/// A set of RNG operators, of which `threshold` must contribute a share to settle a round.
+///
+/// The operators' keys are shares of a single group key, dealt in this
+/// order, see [rng_protocol::threshold].
+#[derive(Debug, Clone)]
+pub struct RngQuorum {
+    pub keys: MerkleVec<PublicKey>,
+    pub threshold: u8,
+}
+
+impl RngQuorum {
+    pub fn new(keys: Vec<PublicKey>, threshold: u8) -> anyhow::Result<Self> {
+        anyhow::ensure!(threshold > 0, "RNG threshold must be at least 1");
+        anyhow::ensure!(
+            usize::from(threshold) <= keys.len(),
+            "RNG threshold {threshold} is more than the {} operators",
+            keys.len()
+        );
+        let mut quorum_keys = MerkleVec::new();
+        for (i, key) in keys.iter().enumerate() {
+            anyhow::ensure!(!keys[..i].contains(key), "Duplicate RNG operator key {key}");
+            quorum_keys.push(*key);
+        }
+        let quorum = RngQuorum {
+            keys: quorum_keys,
+            threshold,
+        };
+        quorum.group_key()?;
+        Ok(quorum)
+    }
+
+    /// Position of an operator in the quorum, counting from 1.
+    pub fn index_of(&self, key: &PublicKey) -> Option<u8> {
+        let position = self.keys.iter().position(|quorum_key| quorum_key == key)?;
+        u8::try_from(position + 1).ok()
+    }
+
+    /// The group key the operators hold shares of.
+    ///
+    /// Fails if their keys aren't all shares of the same one.
+    pub fn group_key(&self) -> anyhow::Result<k256::ecdsa::VerifyingKey> {
+        let keys = self
+            .keys
+            .iter()
+            .map(rng_verifying_key)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        rng_protocol::threshold::group_key(&keys, self.threshold)
+    }
+}
+
+/// One basis point is 1/10,000th of an amount.
+pub const MAX_BPS: u16 = 10_000;
+
+/// The given share of an amount, in basis points.
+pub fn share_bps(amount: Decimal, bps: u16) -> Decimal {
+    amount * Decimal::from(bps) / Decimal::from(MAX_BPS)
+}
+
+/// The house's cut of each settled pot, in basis points, and where it goes.
+#[derive(Debug, Clone)]
+pub struct Rake {
+    pub bps: u16,
+    /// Account the rake is minted to. It must already exist.
+    pub treasury: AccountId,
+}
+
+impl Rake {
+    pub fn new(bps: u16, treasury: AccountId) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            bps <= MAX_BPS,
+            "Rake of {bps} basis points is more than the whole pot"
+        );
+        Ok(Rake { bps, treasury })
+    }
+
+    /// The house's cut of a pot.
+    pub fn of(&self, pot: Decimal) -> Decimal {
+        share_bps(pot, self.bps)
+    }
+}
+
+impl GuessState {
+    /// The RNG server key valid for the given round, if any.
+    pub fn rng_key_for(&self, timestamp: GuessTimestamp) -> Option<PublicKey> {
+        self.rng_keys
+            .iter()
+            .find(|key| key.is_valid_at(timestamp))
+            .map(|key| key.public_key)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -22,13 +176,37 @@ impl MerkleSerialize for GuessState {
         serializer: &mut kolme::MerkleSerializer,
     ) -> Result<(), kolme::MerkleSerialError> {
         let Self {
-            rng_public_key,
+            rng_keys,
+            rng_keys_issued,
+            rng_beacon,
+            rng_commitments,
+            rng_vrf,
+            rng_quorum,
+            rng_stream,
+            round_period,
+            rake,
+            jackpot_bps,
+            jackpot,
+            bet_limits,
             received_funds,
             pending_wagers,
+            commitments,
         } = self;
-        serializer.store(rng_public_key)?;
+        serializer.store(rng_keys)?;
+        serializer.store(rng_keys_issued)?;
+        serializer.store(rng_beacon)?;
+        serializer.store(rng_commitments)?;
+        serializer.store(rng_vrf)?;
+        serializer.store(rng_quorum)?;
+        serializer.store(rng_stream)?;
+        serializer.store(round_period)?;
+        serializer.store(rake)?;
+        serializer.store(jackpot_bps)?;
+        serializer.store(jackpot)?;
+        serializer.store(bet_limits)?;
         serializer.store(received_funds)?;
         serializer.store(pending_wagers)?;
+        serializer.store(commitments)?;
         Ok(())
     }
 }
@@ -39,9 +217,21 @@ impl MerkleDeserialize for GuessState {
         _version: usize,
     ) -> Result<Self, kolme::MerkleSerialError> {
         Ok(Self {
-            rng_public_key: deserializer.load()?,
+            rng_keys: deserializer.load()?,
+            rng_keys_issued: deserializer.load()?,
+            rng_beacon: deserializer.load()?,
+            rng_commitments: deserializer.load()?,
+            rng_vrf: deserializer.load()?,
+            rng_quorum: deserializer.load()?,
+            rng_stream: deserializer.load()?,
+            round_period: deserializer.load()?,
+            rake: deserializer.load()?,
+            jackpot_bps: deserializer.load()?,
+            jackpot: deserializer.load()?,
+            bet_limits: deserializer.load()?,
             received_funds: deserializer.load()?,
             pending_wagers: deserializer.load()?,
+            commitments: deserializer.load()?,
         })
     }
 }
@@ -72,3 +262,72 @@ impl MerkleDeserialize for Wager {
         })
     }
 }
+
+impl MerkleSerialize for RngKey {
+    fn merkle_serialize(&self, serializer: &mut MerkleSerializer) -> Result<(), MerkleSerialError> {
+        let Self {
+            public_key,
+            valid_from,
+            valid_until,
+        } = self;
+        serializer.store(public_key)?;
+        serializer.store(valid_from)?;
+        serializer.store(valid_until)?;
+        Ok(())
+    }
+}
+
+impl MerkleDeserialize for RngKey {
+    fn merkle_deserialize(
+        deserializer: &mut MerkleDeserializer,
+        _version: usize,
+    ) -> Result<Self, MerkleSerialError> {
+        Ok(Self {
+            public_key: deserializer.load()?,
+            valid_from: deserializer.load()?,
+            valid_until: deserializer.load()?,
+        })
+    }
+}
+
+impl MerkleSerialize for RngQuorum {
+    fn merkle_serialize(&self, serializer: &mut MerkleSerializer) -> Result<(), MerkleSerialError> {
+        let Self { keys, threshold } = self;
+        serializer.store(keys)?;
+        serializer.store(threshold)?;
+        Ok(())
+    }
+}
+
+impl MerkleDeserialize for RngQuorum {
+    fn merkle_deserialize(
+        deserializer: &mut MerkleDeserializer,
+        _version: usize,
+    ) -> Result<Self, MerkleSerialError> {
+        Ok(Self {
+            keys: deserializer.load()?,
+            threshold: deserializer.load()?,
+        })
+    }
+}
+
+impl MerkleSerialize for Rake {
+    fn merkle_serialize(&self, serializer: &mut MerkleSerializer) -> Result<(), MerkleSerialError> {
+        let Self { bps, treasury } = self;
+        serializer.store(bps)?;
+        serializer.store(treasury)?;
+        Ok(())
+    }
+}
+
+impl MerkleDeserialize for Rake {
+    fn merkle_deserialize(
+        deserializer: &mut MerkleDeserializer,
+        _version: usize,
+    ) -> Result<Self, MerkleSerialError> {
+        Ok(Self {
+            bps: deserializer.load()?,
+            treasury: deserializer.load()?,
+        })
+    }
+}
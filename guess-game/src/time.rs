@@ -1,8 +1,8 @@
-use std::fmt::Display;
+use std::{fmt::Display, str::FromStr};
 
 use kolme::*;
 
-/// A guess timestamp, which is minutes from the epoch.
+/// A guess timestamp, which is the number of rounds since the epoch.
 #[derive(
     Debug, Clone, Copy, serde::Serialize, serde::Deserialize, PartialEq, Eq, PartialOrd, Ord,
 )]
@@ -20,18 +20,54 @@ impl FromMerkleKey for GuessTimestamp {
     }
 }
 
+impl MerkleSerialize for GuessTimestamp {
+    fn merkle_serialize(&self, serializer: &mut MerkleSerializer) -> Result<(), MerkleSerialError> {
+        serializer.store(&self.0)
+    }
+}
+
+impl MerkleDeserialize for GuessTimestamp {
+    fn merkle_deserialize(
+        deserializer: &mut MerkleDeserializer,
+        _version: usize,
+    ) -> Result<Self, MerkleSerialError> {
+        deserializer.load().map(Self)
+    }
+}
+
 impl GuessTimestamp {
     /// Find the next guess timestamp after the given timestamp.
     ///
     /// Panics if given a timestamp from before the Unix epoch.
-    pub fn after(timestamp: Timestamp) -> Self {
+    pub fn after(timestamp: Timestamp, period: RoundPeriod) -> Self {
         GuessTimestamp(
             u64::try_from(timestamp.as_second())
                 .expect("GuessTimestamp::after: received timestamp from before the epoch")
-                / 60
+                / period.0
                 + 1,
         )
     }
+
+    /// When this round of betting finishes.
+    pub fn to_timestamp(self, period: RoundPeriod) -> Timestamp {
+        let seconds = self.0 * period.0;
+        Timestamp::from_second(seconds as i64).unwrap()
+    }
+
+    /// Number of rounds from `earlier` until this one, zero if `earlier` is later.
+    pub fn rounds_since(self, earlier: GuessTimestamp) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+
+    /// The round immediately after this one.
+    pub fn next(self) -> Self {
+        GuessTimestamp(self.0 + 1)
+    }
+
+    /// The round immediately before this one, if this isn't the very first.
+    pub fn previous(self) -> Option<Self> {
+        self.0.checked_sub(1).map(GuessTimestamp)
+    }
 }
 
 impl Display for GuessTimestamp {
@@ -40,23 +76,50 @@ impl Display for GuessTimestamp {
     }
 }
 
-impl From<&GuessTimestamp> for Timestamp {
-    fn from(GuessTimestamp(minutes): &GuessTimestamp) -> Self {
-        let seconds = minutes * 60;
-        Timestamp::from_second(seconds as i64).unwrap()
+impl From<u64> for GuessTimestamp {
+    fn from(value: u64) -> Self {
+        GuessTimestamp(value)
     }
 }
 
-impl From<GuessTimestamp> for Timestamp {
-    fn from(value: GuessTimestamp) -> Self {
-        Timestamp::from(&value)
+/// The length of a round of betting, set at genesis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoundPeriod(u64);
+
+impl RoundPeriod {
+    pub fn as_secs(self) -> u64 {
+        self.0
+    }
+}
+
+/// Parses a number of seconds, optionally with an `s`, `m` or `h` suffix.
+impl FromStr for RoundPeriod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let secs = rng_protocol::parse_duration(s)?;
+        anyhow::ensure!(secs > 0, "Round period must not be zero");
+        Ok(RoundPeriod(secs))
     }
 }
 
-impl TryFrom<i64> for GuessTimestamp {
-    type Error = anyhow::Error;
+impl Display for RoundPeriod {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}s", self.0)
+    }
+}
+
+impl MerkleSerialize for RoundPeriod {
+    fn merkle_serialize(&self, serializer: &mut MerkleSerializer) -> Result<(), MerkleSerialError> {
+        serializer.store(&self.0)
+    }
+}
 
-    fn try_from(value: i64) -> std::result::Result<Self, Self::Error> {
-        Ok(GuessTimestamp(u64::try_from(value)?))
+impl MerkleDeserialize for RoundPeriod {
+    fn merkle_deserialize(
+        deserializer: &mut MerkleDeserializer,
+        _version: usize,
+    ) -> Result<Self, MerkleSerialError> {
+        deserializer.load().map(Self)
     }
 }
@@ -0,0 +1,365 @@
+//! Daily bundles of every result, served from `/bundle/{date}`.
+//!
+//! A bundle holds the default stream's result for every round starting on
+//! a UTC day, and a signed Merkle root over them. Leaf hashes are
+//! `SHA-256(0x00 || signature || serialized)` with the raw signature bytes.
+//! Inner nodes are `SHA-256(0x01 || left || right)`, an odd node at the end
+//! of a level moves up unchanged.
+use std::ops::RangeInclusive;
+
+use anyhow::Context;
+use k256::ecdsa::VerifyingKey;
+use k256::sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+
+use crate::{verify, RngResult, Signed, Verified};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Every result of a UTC day, see the [module docs](self).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngBundle {
+    pub root: Signed<BundleRoot>,
+    /// One result per round, in order.
+    pub results: Vec<Signed<RngResult>>,
+}
+
+/// What gets signed for a bundle, by the key for its last round.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BundleRoot {
+    /// The day, as `YYYY-MM-DD`.
+    pub date: String,
+    /// Length of each round in seconds.
+    pub round_period: u64,
+    /// First round starting on the day.
+    pub first: u64,
+    /// Last round starting on the day.
+    pub last: u64,
+    /// Hex-encoded Merkle root of the results.
+    pub root: String,
+}
+
+/// Merkle root over the given leaf hashes, the SHA-256 of no input if there are none.
+pub fn merkle_root(mut level: Vec<[u8; 32]>) -> [u8; 32] {
+    if level.is_empty() {
+        return Sha256::digest([]).into();
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => Sha256::new()
+                    .chain_update([0x01])
+                    .chain_update(left)
+                    .chain_update(right)
+                    .finalize()
+                    .into(),
+                [odd] => *odd,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+/// Leaf hash of a result in a bundle.
+pub fn bundle_leaf(result: &Signed<RngResult>) -> anyhow::Result<[u8; 32]> {
+    Ok(Sha256::new()
+        .chain_update([0x00])
+        .chain_update(hex::decode(&result.signature)?)
+        .chain_update(&result.serialized)
+        .finalize()
+        .into())
+}
+
+/// Rounds starting on the given day, counted from the epoch.
+pub fn day_rounds(day: u64, round_period: u64) -> RangeInclusive<u64> {
+    let first = (day * SECS_PER_DAY).div_ceil(round_period);
+    let next = ((day + 1) * SECS_PER_DAY).div_ceil(round_period);
+    first..=next - 1
+}
+
+/// Days since the epoch for a `YYYY-MM-DD` date, from 1970 to 9999.
+pub fn parse_date(date: &str) -> anyhow::Result<u64> {
+    let invalid = || format!("Invalid date {date:?}, expected YYYY-MM-DD");
+    let mut parts = date.splitn(3, '-').map(str::parse::<u64>);
+    let mut next =
+        || -> anyhow::Result<u64> { parts.next().with_context(invalid)?.with_context(invalid) };
+    let (year, month, day) = (next()?, next()?, next()?);
+    anyhow::ensure!(
+        (1970..=9999).contains(&year) && (1..=12).contains(&month),
+        invalid()
+    );
+    // Days from civil, see https://howardhinnant.github.io/date_algorithms.html
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year =
+        (153 * ((month + 9) % 12) + 2) / 5 + day.checked_sub(1).with_context(invalid)?;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+    // Also rules out days past the end of the month, and unpadded numbers
+    anyhow::ensure!(format_date(days) == date, invalid());
+    Ok(days)
+}
+
+/// `YYYY-MM-DD` for a day counted from the epoch.
+pub fn format_date(days: u64) -> String {
+    // Civil from days, the inverse of the above
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = (shifted_month + 2) % 12 + 1;
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Check a whole bundle: its signed root, and every result in it with [verify].
+///
+/// The bundle must cover exactly the rounds starting on its day, and
+/// every result must be for the default stream's full range.
+pub fn verify_bundle(
+    bundle: &RngBundle,
+    public_keys: &[VerifyingKey],
+    hmac_key: Option<&[u8]>,
+) -> anyhow::Result<Vec<Verified>> {
+    let signer = bundle.root.signer()?;
+    anyhow::ensure!(
+        public_keys.contains(&signer),
+        "Bundle signed by {}, which is not an expected public key",
+        hex::encode(signer.to_sec1_bytes())
+    );
+    let root = bundle.root.payload()?;
+    anyhow::ensure!(root.round_period > 0, "Bundle has a zero round period");
+    let rounds = day_rounds(parse_date(&root.date)?, root.round_period);
+    anyhow::ensure!(
+        (root.first, root.last) == (*rounds.start(), *rounds.end()),
+        "Bundle covers rounds {} to {}, but {} has rounds {} to {}",
+        root.first,
+        root.last,
+        root.date,
+        rounds.start(),
+        rounds.end()
+    );
+    anyhow::ensure!(
+        rounds.clone().count() == bundle.results.len(),
+        "Bundle has {} results for {} rounds",
+        bundle.results.len(),
+        rounds.count()
+    );
+    let mut verified = Vec::with_capacity(bundle.results.len());
+    for (timestamp, response) in rounds.zip(&bundle.results) {
+        let result = verify(response, public_keys, hmac_key)
+            .with_context(|| format!("Round {timestamp} failed verification"))?;
+        anyhow::ensure!(
+            result.result.timestamp == timestamp,
+            "Expected round {timestamp}, found round {}",
+            result.result.timestamp
+        );
+        anyhow::ensure!(
+            result.result.stream.is_none() && result.result.range()?.is_none(),
+            "Round {timestamp} is not from the default stream's full range"
+        );
+        verified.push(result);
+    }
+    let leaves = bundle
+        .results
+        .iter()
+        .map(bundle_leaf)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let actual = hex::encode(merkle_root(leaves));
+    anyhow::ensure!(
+        actual == root.root,
+        "Bundle Merkle root mismatch: signed {}, computed {actual}",
+        root.root
+    );
+    Ok(verified)
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+    use serde::Serialize;
+
+    use super::*;
+    use crate::{canonical, derive_number, hmac_seed};
+
+    const HMAC_KEY: &[u8] = b"bundle test secret";
+
+    fn sign<T: Serialize>(key: &SigningKey, fields: T) -> Signed<T> {
+        let serialized = canonical(&fields).unwrap();
+        let (signature, recovery_id) = key.sign_recoverable(serialized.as_bytes()).unwrap();
+        Signed {
+            fields,
+            signature: hex::encode(signature.to_bytes()),
+            recovery_id: recovery_id.to_byte(),
+            serialized,
+        }
+    }
+
+    fn result(key: &SigningKey, timestamp: u64, seed: [u8; 32]) -> Signed<RngResult> {
+        sign(
+            key,
+            RngResult {
+                number: derive_number(&seed, None),
+                timestamp,
+                stream: None,
+                seed: Some(hex::encode(seed)),
+                proof: None,
+                previous_signature_hash: None,
+                min: None,
+                max: None,
+            },
+        )
+    }
+
+    fn sign_root(
+        key: &SigningKey,
+        date: &str,
+        round_period: u64,
+        results: &[Signed<RngResult>],
+    ) -> Signed<BundleRoot> {
+        let rounds = day_rounds(parse_date(date).unwrap(), round_period);
+        let leaves = results
+            .iter()
+            .map(|result| bundle_leaf(result).unwrap())
+            .collect();
+        sign(
+            key,
+            BundleRoot {
+                date: date.to_owned(),
+                round_period,
+                first: *rounds.start(),
+                last: *rounds.end(),
+                root: hex::encode(merkle_root(leaves)),
+            },
+        )
+    }
+
+    /// A day of hourly HMAC rounds, signed by `key`.
+    fn bundle(key: &SigningKey, date: &str) -> RngBundle {
+        let results = day_rounds(parse_date(date).unwrap(), 3600)
+            .map(|timestamp| {
+                result(
+                    key,
+                    timestamp,
+                    hmac_seed(HMAC_KEY, None, timestamp).unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        RngBundle {
+            root: sign_root(key, date, 3600, &results),
+            results,
+        }
+    }
+
+    #[test]
+    fn date_round_trip() {
+        for (date, days) in [
+            ("1970-01-01", 0),
+            ("1970-03-01", 59),
+            ("2000-02-29", 11016),
+            ("2000-03-01", 11017),
+            ("2024-02-29", 19782),
+            ("2100-03-01", 47541),
+            ("9999-12-31", 2932896),
+        ] {
+            assert_eq!(parse_date(date).unwrap(), days, "{date}");
+            assert_eq!(format_date(days), date);
+        }
+        for days in (0..1_000_000).step_by(97) {
+            assert_eq!(parse_date(&format_date(days)).unwrap(), days);
+        }
+    }
+
+    #[test]
+    fn invalid_dates_are_rejected() {
+        for date in [
+            "2023-02-29",
+            "2100-02-29",
+            "2024-04-31",
+            "2024-01-32",
+            "2024-01-00",
+            "2024-00-10",
+            "2024-13-01",
+            "2024-1-05",
+            "2024-01-5",
+            "02024-01-05",
+            "+2024-01-05",
+            "1969-12-31",
+            "0000-01-01",
+            "10000-01-01",
+            "2024-01",
+            "2024-01-05T00:00",
+            "",
+        ] {
+            assert!(parse_date(date).is_err(), "{date}");
+        }
+    }
+
+    /// Every round belongs to exactly one day, the one it starts on.
+    #[test]
+    fn day_rounds_partition_time() {
+        for round_period in [1, 7, 61, 3600, 3601, 86399, 86400, 86401, 200_000] {
+            let mut next = 0;
+            for day in 0..1000 {
+                let rounds = day_rounds(day, round_period);
+                assert_eq!(*rounds.start(), next, "day {day}, period {round_period}");
+                for round in [*rounds.start(), *rounds.end()] {
+                    if rounds.contains(&round) {
+                        assert_eq!(round * round_period / SECS_PER_DAY, day);
+                    }
+                }
+                next = rounds.end() + 1;
+            }
+        }
+        // 86400 / 7 isn't whole, so some days get one more round than others.
+        assert_eq!(day_rounds(0, 7), 0..=12342);
+        assert_eq!(day_rounds(1, 7), 12343..=24685);
+        assert_eq!(day_rounds(2, 7), 24686..=37028);
+        // Longer than a day, so some days have no rounds at all.
+        assert!(day_rounds(1, 200_000).is_empty());
+    }
+
+    #[test]
+    fn bundle_verifies() {
+        let key = SigningKey::from_slice(&[1; 32]).unwrap();
+        let bundle = bundle(&key, "2024-02-29");
+        let verified = verify_bundle(&bundle, &[*key.verifying_key()], Some(HMAC_KEY)).unwrap();
+        assert_eq!(verified.len(), 24);
+        assert!(verify_bundle(&bundle, &[*key.verifying_key()], None).is_ok());
+
+        let other = SigningKey::from_slice(&[2; 32]).unwrap();
+        assert!(verify_bundle(&bundle, &[*other.verifying_key()], None).is_err());
+    }
+
+    #[test]
+    fn tampered_result_is_rejected() {
+        let key = SigningKey::from_slice(&[1; 32]).unwrap();
+        let public_keys = [*key.verifying_key()];
+
+        // Changed after signing
+        let mut tampered = bundle(&key, "2024-02-29");
+        tampered.results[5].fields.number ^= 1;
+        assert!(verify_bundle(&tampered, &public_keys, None).is_err());
+
+        // Validly signed, but not the result the root was signed over
+        let mut tampered = bundle(&key, "2024-02-29");
+        let timestamp = tampered.results[5].fields.timestamp;
+        tampered.results[5] = result(&key, timestamp, [0xab; 32]);
+        let err = verify_bundle(&tampered, &public_keys, None).unwrap_err();
+        assert!(err.to_string().contains("Merkle root mismatch"), "{err}");
+        // And caught by the seed check too, given the HMAC secret
+        assert!(verify_bundle(&tampered, &public_keys, Some(HMAC_KEY)).is_err());
+
+        // Missing a round
+        let mut tampered = bundle(&key, "2024-02-29");
+        tampered.results.pop();
+        assert!(verify_bundle(&tampered, &public_keys, None).is_err());
+    }
+}
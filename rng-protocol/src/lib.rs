@@ -0,0 +1,348 @@
+//! Wire format of the rng-server, shared by the server, its clients and
+//! third-party verifiers.
+//!
+//! Every signed response is a [Signed] payload. The signature covers the
+//! `serialized` field, which holds the payload's [canonical] JSON: its
+//! fields in the order declared here, leaving out absent optional fields.
+//! [verify] checks a round's result end to end, [verify_bundle] a whole
+//! day of them.
+mod bundle;
+pub mod threshold;
+mod verify;
+pub mod vrf;
+
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use k256::sha2::{Digest, Sha256};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub use bundle::{
+    bundle_leaf, day_rounds, format_date, merkle_root, parse_date, verify_bundle, BundleRoot,
+    RngBundle,
+};
+pub use verify::{verify, SeedCheck, Verified};
+
+/// Most rounds served by a single `/numbers` request.
+pub const MAX_RANGE: u64 = 1440;
+
+/// Longest allowed stream name, in bytes.
+pub const MAX_STREAM_NAME: usize = 64;
+
+/// A signed response from the server.
+///
+/// Only `serialized` is signed. The payload's fields are repeated next to
+/// it for convenience, [Signed::payload] checks that they match.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Signed<T> {
+    #[serde(flatten)]
+    pub fields: T,
+    /// Hex-encoded ECDSA signature over `serialized`.
+    pub signature: String,
+    pub recovery_id: u8,
+    /// The payload's [canonical] serialization.
+    pub serialized: String,
+}
+
+impl<T: Serialize + DeserializeOwned + PartialEq> Signed<T> {
+    /// Recover the key that signed this response.
+    pub fn signer(&self) -> anyhow::Result<VerifyingKey> {
+        let signature = Signature::from_slice(&hex::decode(&self.signature)?)?;
+        let recovery_id = RecoveryId::from_byte(self.recovery_id).context("Invalid recovery ID")?;
+        Ok(VerifyingKey::recover_from_msg(
+            self.serialized.as_bytes(),
+            &signature,
+            recovery_id,
+        )?)
+    }
+
+    /// Parse the signed payload.
+    ///
+    /// Fails unless `serialized` is canonical, so nothing can hide in
+    /// fields this version doesn't know about, or if the repeated fields
+    /// don't match it.
+    pub fn payload(&self) -> anyhow::Result<T> {
+        let payload: T =
+            serde_json::from_str(&self.serialized).context("Invalid serialized payload")?;
+        anyhow::ensure!(
+            canonical(&payload)? == self.serialized,
+            "Serialized payload is not canonical: {}",
+            self.serialized
+        );
+        anyhow::ensure!(
+            payload == self.fields,
+            "Response fields do not match the signed payload {}",
+            self.serialized
+        );
+        Ok(payload)
+    }
+}
+
+/// Serialize a payload the way the server signs it.
+pub fn canonical<T: Serialize>(payload: &T) -> anyhow::Result<String> {
+    Ok(serde_json::to_string(payload)?)
+}
+
+/// A round's result, served from `/number/{timestamp}`, `/numbers` and `/stream`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngResult {
+    pub number: u32,
+    /// Round number, counting round periods since the epoch.
+    pub timestamp: u64,
+    /// Named stream the round belongs to, [None] for the default stream.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+    /// Hex-encoded seed the number was derived from, see [derive_number].
+    ///
+    /// The SHA-256 of the seed is the round's commitment, see [RngCommitment].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<String>,
+    /// Hex-encoded ECVRF proof for the seed, only present in VRF mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proof: Option<String>,
+    /// Hex-encoded [signature_hash] of the previous round's signature, only present in beacon mode.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous_signature_hash: Option<String>,
+    /// Lower bound the number was drawn from, if a range was requested.
+    ///
+    /// The range is signed along with the number, so an answer for one
+    /// range can't be passed off as the answer for another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u32>,
+    /// Upper bound the number was drawn from, inclusive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u32>,
+}
+
+impl RngResult {
+    /// The range the number was drawn from, [None] for the full u32 range.
+    pub fn range(&self) -> anyhow::Result<Option<(u32, u32)>> {
+        match (self.min, self.max) {
+            (None, None) => Ok(None),
+            (Some(min), Some(max)) if min <= max => Ok(Some((min, max))),
+            _ => anyhow::bail!("Invalid range in RNG result"),
+        }
+    }
+}
+
+/// One operator's contribution to a round, served from `/share/{timestamp}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngShare {
+    /// Hex-encoded proof of this operator's share of the round's seed,
+    /// see [threshold].
+    pub share: String,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+}
+
+/// Commitment to an upcoming round, served from `/commitment/{timestamp}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngCommitment {
+    /// Hex-encoded SHA-256 of the round's seed.
+    pub commitment: String,
+    pub timestamp: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream: Option<String>,
+}
+
+/// The server's signing keys, served from `/public-keys`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngKeySet {
+    pub keys: Vec<RngKeyInfo>,
+    /// Round this key set was issued in, signed by the key valid for that round.
+    pub issued: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngKeyInfo {
+    /// Hex-encoded compressed public key.
+    pub public_key: String,
+    /// First round the key is valid for.
+    pub valid_from: Option<u64>,
+    /// First round the key is no longer valid for.
+    pub valid_until: Option<u64>,
+}
+
+/// The server's status, served from `/readyz`.
+///
+/// Only sent once the key for the current round has passed a signing
+/// self-test. Otherwise `/readyz` fails with `503 Service Unavailable`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngReadiness {
+    /// Version of the server build.
+    pub version: String,
+    /// Hex-encoded compressed public key valid for the current round.
+    pub public_key: String,
+    /// Short identifier for the same key, as used in the server's metrics.
+    pub fingerprint: String,
+    /// The server's clock, in seconds since the epoch.
+    pub server_time: u64,
+    pub current_round: u64,
+    /// Length of each round in seconds.
+    pub round_period: u64,
+}
+
+/// Error code for a round that hasn't started yet.
+///
+/// Sent with `425 Too Early` and a `Retry-After` header giving the
+/// seconds until the round starts.
+pub const ROUND_NOT_READY: &str = "round_not_ready";
+
+/// Body of an error response.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RngError {
+    /// Machine-readable error code, such as [ROUND_NOT_READY].
+    pub code: String,
+    pub message: String,
+}
+
+/// Whether a stream name is allowed: 1 to [MAX_STREAM_NAME] ASCII letters,
+/// digits, `-` or `_`.
+pub fn is_valid_stream_name(name: &str) -> bool {
+    (1..=MAX_STREAM_NAME).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Parse a duration such as `90`, `15s`, `5m` or `1h` into seconds.
+///
+/// Used for round periods and other durations on both the server and the game.
+pub fn parse_duration(s: &str) -> anyhow::Result<u64> {
+    let (value, unit) = s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()));
+    let multiplier = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        _ => anyhow::bail!("Invalid duration unit {unit:?}, expected s, m or h"),
+    };
+    value
+        .parse::<u64>()
+        .with_context(|| format!("Invalid duration {s:?}"))?
+        .checked_mul(multiplier)
+        .with_context(|| format!("Duration {s} is too long"))
+}
+
+/// The input a round's seed is derived from, as HMAC message or VRF alpha.
+///
+/// The default stream uses the timestamp as 8 big-endian bytes. Named
+/// streams prefix that with the name's length as a single byte and the
+/// name itself, so each stream gets independent numbers and no two
+/// streams ever share an input.
+pub fn seed_input(stream: Option<&str>, timestamp: u64) -> anyhow::Result<Vec<u8>> {
+    let mut input = vec![];
+    if let Some(name) = stream {
+        anyhow::ensure!(is_valid_stream_name(name), "Invalid stream name {name:?}");
+        // At most MAX_STREAM_NAME bytes, so this fits
+        input.push(name.len() as u8);
+        input.extend_from_slice(name.as_bytes());
+    }
+    input.extend_from_slice(&timestamp.to_be_bytes());
+    Ok(input)
+}
+
+/// Derive a round's seed in HMAC mode.
+pub fn hmac_seed(
+    hmac_key: &[u8],
+    stream: Option<&str>,
+    timestamp: u64,
+) -> anyhow::Result<[u8; 32]> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_key)
+        .map_err(|_| anyhow::anyhow!("Invalid HMAC key"))?;
+    mac.update(&seed_input(stream, timestamp)?);
+    Ok(mac.finalize().into_bytes().into())
+}
+
+/// Derive a round's number from its seed, optionally drawn from a range.
+///
+/// Without a range the number is the seed's first 4 bytes, big-endian.
+/// With one it's drawn uniformly from `min` to `max` inclusive, reading
+/// the seed followed by `SHA-256(seed || i)` for `i = 1, 2, ...` (a 4-byte
+/// big-endian counter) as big-endian u32 words, and skipping words from the
+/// top end of the u32 range that would bias the result.
+pub fn derive_number(seed: &[u8; 32], range: Option<(u32, u32)>) -> u32 {
+    let (min, max) = range.unwrap_or((0, u32::MAX));
+    let span = u64::from(max - min) + 1;
+    // Largest multiple of span that fits in a u32
+    let limit = (1 << 32) - (1 << 32) % span;
+    let mut block = *seed;
+    let mut counter = 0u32;
+    loop {
+        for word in block.chunks_exact(4) {
+            let word = u64::from(u32::from_be_bytes([word[0], word[1], word[2], word[3]]));
+            if word < limit {
+                // Less than span, so fits in a u32
+                return min + (word % span) as u32;
+            }
+        }
+        counter = counter.wrapping_add(1);
+        block = Sha256::new()
+            .chain_update(seed)
+            .chain_update(counter.to_be_bytes())
+            .finalize()
+            .into();
+    }
+}
+
+/// Hash of a signature as used for beacon links: SHA-256 over the raw
+/// bytes of the hex-encoded signature.
+pub fn signature_hash(signature: &str) -> anyhow::Result<[u8; 32]> {
+    Ok(Sha256::digest(hex::decode(signature)?).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Seeds that are the hashes of `0, 1, 2, ...`, so they look random.
+    fn seeds(count: u32) -> impl Iterator<Item = [u8; 32]> {
+        (0..count).map(|i| Sha256::digest(i.to_be_bytes()).into())
+    }
+
+    #[test]
+    fn single_value_range() {
+        for seed in seeds(100).chain([[0; 32], [0xff; 32]]) {
+            assert_eq!(derive_number(&seed, Some((7, 7))), 7);
+            assert_eq!(derive_number(&seed, Some((u32::MAX, u32::MAX))), u32::MAX);
+        }
+    }
+
+    #[test]
+    fn full_range_is_the_first_word() {
+        for seed in seeds(100).chain([[0; 32], [0xff; 32]]) {
+            let first = u32::from_be_bytes(seed[..4].try_into().unwrap());
+            assert_eq!(derive_number(&seed, None), first);
+            assert_eq!(derive_number(&seed, Some((0, u32::MAX))), first);
+        }
+    }
+
+    #[test]
+    fn small_range_stays_in_bounds() {
+        let mut seen = [false; 6];
+        for seed in seeds(1000) {
+            let number = derive_number(&seed, Some((1, 6)));
+            assert!((1..=6).contains(&number), "{number}");
+            seen[number as usize - 1] = true;
+        }
+        assert_eq!(seen, [true; 6]);
+    }
+
+    /// With a span of 2^31 + 1, every word from the seed is rejected and
+    /// the number comes from the first word of `SHA-256(seed || 1)`.
+    #[test]
+    fn rejected_seed_is_rehashed() {
+        let seed = [0xff; 32];
+        let number = derive_number(&seed, Some((0, 1 << 31)));
+        let rehashed = Sha256::new()
+            .chain_update(seed)
+            .chain_update(1u32.to_be_bytes())
+            .finalize();
+        assert_eq!(
+            number,
+            u32::from_be_bytes(rehashed[..4].try_into().unwrap())
+        );
+        assert_eq!(number, 0x3055_3e78);
+    }
+}
@@ -0,0 +1,232 @@
+//! Threshold ECVRF, so a round's output doesn't depend on which operators
+//! contributed to it.
+//!
+//! A dealer splits a group key `x` into shares with Shamir's secret sharing,
+//! one per operator, and only keeps the group public key. Operator `i`,
+//! counting from 1 in quorum order, signs with its share `x_i` and proves
+//! `Gamma_i = H * x_i` with the same proof as [vrf], where `H` is the round's
+//! input hashed to the curve under the group public key. Any `threshold`
+//! valid shares interpolate to `Gamma = H * x`, so the output is always the
+//! group key's own VRF output, whichever operators took part.
+use anyhow::{Context, Result};
+use k256::ecdsa::{SigningKey, VerifyingKey};
+use k256::elliptic_curve::rand_core::CryptoRngCore;
+use k256::elliptic_curve::Field;
+use k256::{NonZeroScalar, ProjectivePoint, Scalar};
+
+use crate::vrf::{self, PROOF_LEN};
+
+/// One operator's contribution to a round.
+pub struct Share<'a> {
+    /// Position of the operator in the quorum, counting from 1.
+    pub index: u8,
+    pub public_key: VerifyingKey,
+    /// Encoded proof, as from [prove_share].
+    pub proof: &'a [u8],
+}
+
+/// Deal a new group key as `operators` shares, any `threshold` of which can
+/// produce its output.
+///
+/// Returns the group public key and the shares, in quorum order. The group
+/// secret itself is dropped.
+pub fn split(
+    rng: &mut impl CryptoRngCore,
+    threshold: u8,
+    operators: u8,
+) -> Result<(VerifyingKey, Vec<SigningKey>)> {
+    anyhow::ensure!(
+        (1..=operators).contains(&threshold),
+        "Threshold {threshold} must be between 1 and the {operators} operators"
+    );
+    let secret = NonZeroScalar::random(&mut *rng);
+    let coefficients = std::iter::once(*secret)
+        .chain((1..threshold).map(|_| Scalar::random(&mut *rng)))
+        .collect::<Vec<_>>();
+    let shares = (1..=operators)
+        .map(|index| {
+            let at = Scalar::from(u64::from(index));
+            let value = coefficients
+                .iter()
+                .rev()
+                .fold(Scalar::ZERO, |acc, coefficient| acc * at + coefficient);
+            Option::<NonZeroScalar>::from(NonZeroScalar::new(value))
+                .map(SigningKey::from)
+                .context("Dealt a zero share, try again")
+        })
+        .collect::<Result<_>>()?;
+    Ok((VerifyingKey::from(&SigningKey::from(secret)), shares))
+}
+
+/// The group public key the operators' keys are shares of, in quorum order.
+///
+/// Fails unless every key lies on the same polynomial, otherwise different
+/// sets of operators would produce different outputs.
+pub fn group_key(keys: &[VerifyingKey], threshold: u8) -> Result<VerifyingKey> {
+    anyhow::ensure!(
+        threshold >= 1 && usize::from(threshold) <= keys.len(),
+        "Threshold {threshold} must be between 1 and the {} operators",
+        keys.len()
+    );
+    anyhow::ensure!(
+        keys.len() <= usize::from(u8::MAX),
+        "Too many operators, at most {} are supported",
+        u8::MAX
+    );
+    let points = keys
+        .iter()
+        .map(|key| ProjectivePoint::from(*key.as_affine()))
+        .collect::<Vec<_>>();
+    let base = (1..=threshold).collect::<Vec<_>>();
+    let interpolate = |at: Scalar| {
+        base.iter().fold(ProjectivePoint::IDENTITY, |acc, &index| {
+            acc + points[usize::from(index) - 1] * lagrange(&base, index, at)
+        })
+    };
+    for (index, point) in (1..=u8::MAX).zip(&points).skip(base.len()) {
+        anyhow::ensure!(
+            interpolate(Scalar::from(u64::from(index))) == *point,
+            "Operator key {index} is not a share of the same group key as the ones before it"
+        );
+    }
+    VerifyingKey::from_affine(interpolate(Scalar::ZERO).to_affine())
+        .context("Operator keys interpolate to an invalid group key")
+}
+
+/// Prove this operator's share of the output for the given input.
+pub fn prove_share(
+    share: &SigningKey,
+    group_key: &VerifyingKey,
+    alpha: &[u8],
+) -> Result<[u8; PROOF_LEN]> {
+    let h = vrf::encode_to_curve(&ProjectivePoint::from(*group_key.as_affine()), alpha)?;
+    let (proof, _gamma) = vrf::prove_point(*share.as_nonzero_scalar().as_ref(), h)?;
+    Ok(proof)
+}
+
+/// Check every share and combine them into the group's VRF output.
+///
+/// Needs at least `threshold` shares, from distinct operators.
+pub fn combine(
+    group_key: &VerifyingKey,
+    threshold: u8,
+    alpha: &[u8],
+    shares: &[Share],
+) -> Result<[u8; 32]> {
+    anyhow::ensure!(
+        shares.len() >= usize::from(threshold),
+        "Needs {threshold} shares, but only {} were provided",
+        shares.len()
+    );
+    let indices = shares.iter().map(|share| share.index).collect::<Vec<_>>();
+    for (i, index) in indices.iter().enumerate() {
+        anyhow::ensure!(*index > 0, "Operator indices count from 1");
+        anyhow::ensure!(
+            !indices[..i].contains(index),
+            "Multiple shares from operator {index}"
+        );
+    }
+    let h = vrf::encode_to_curve(&ProjectivePoint::from(*group_key.as_affine()), alpha)?;
+    let mut gamma = ProjectivePoint::IDENTITY;
+    for share in shares {
+        let gamma_i = vrf::verify_point(
+            ProjectivePoint::from(*share.public_key.as_affine()),
+            h,
+            share.proof,
+        )
+        .with_context(|| format!("Invalid share from operator {}", share.index))?;
+        gamma += gamma_i * lagrange(&indices, share.index, Scalar::ZERO);
+    }
+    vrf::proof_to_hash(&gamma)
+}
+
+/// Lagrange coefficient for `index` among `indices`, evaluated at `at`.
+///
+/// The indices must be distinct.
+fn lagrange(indices: &[u8], index: u8, at: Scalar) -> Scalar {
+    let x_i = Scalar::from(u64::from(index));
+    let (numerator, denominator) = indices
+        .iter()
+        .filter(|&&other| other != index)
+        .map(|&other| Scalar::from(u64::from(other)))
+        .fold((Scalar::ONE, Scalar::ONE), |(num, den), x_j| {
+            (num * (at - x_j), den * (x_i - x_j))
+        });
+    numerator * Option::<Scalar>::from(denominator.invert()).expect("Indices must be distinct")
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::OsRng;
+
+    use super::*;
+
+    const ALPHA: &[u8] = b"round 42";
+
+    /// Deal 3-of-5 shares and prove every operator's share of [ALPHA].
+    fn deal() -> (VerifyingKey, Vec<VerifyingKey>, Vec<[u8; PROOF_LEN]>) {
+        let (group_key, shares) = split(&mut OsRng, 3, 5).unwrap();
+        let keys = shares.iter().map(VerifyingKey::from).collect();
+        let proofs = shares
+            .iter()
+            .map(|share| prove_share(share, &group_key, ALPHA).unwrap())
+            .collect();
+        (group_key, keys, proofs)
+    }
+
+    fn shares<'a>(
+        keys: &[VerifyingKey],
+        proofs: &'a [[u8; PROOF_LEN]],
+        indices: &[u8],
+    ) -> Vec<Share<'a>> {
+        indices
+            .iter()
+            .map(|&index| Share {
+                index,
+                public_key: keys[usize::from(index) - 1],
+                proof: &proofs[usize::from(index) - 1],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn any_quorum_gives_the_same_output() {
+        let (group_key, keys, proofs) = deal();
+        let expected = combine(&group_key, 3, ALPHA, &shares(&keys, &proofs, &[1, 2, 3])).unwrap();
+        for indices in [&[1, 2, 4][..], &[3, 5, 1], &[2, 4, 5], &[5, 4, 3, 2, 1]] {
+            let output = combine(&group_key, 3, ALPHA, &shares(&keys, &proofs, indices)).unwrap();
+            assert_eq!(output, expected, "operators {indices:?}");
+        }
+    }
+
+    #[test]
+    fn too_few_shares_are_rejected() {
+        let (group_key, keys, proofs) = deal();
+        let err = combine(&group_key, 3, ALPHA, &shares(&keys, &proofs, &[1, 4])).unwrap_err();
+        assert!(err.to_string().contains("Needs 3 shares"), "{err}");
+    }
+
+    #[test]
+    fn duplicate_operators_are_rejected() {
+        let (group_key, keys, proofs) = deal();
+        let err = combine(&group_key, 3, ALPHA, &shares(&keys, &proofs, &[2, 5, 2])).unwrap_err();
+        assert!(
+            err.to_string().contains("Multiple shares from operator 2"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn group_key_needs_every_operator_in_order() {
+        let (group_key, keys, _proofs) = deal();
+        assert_eq!(super::group_key(&keys, 3).unwrap(), group_key);
+
+        let mut reordered = keys.clone();
+        reordered.swap(0, 1);
+        assert!(super::group_key(&reordered, 3).is_err());
+
+        let mut foreign = keys.clone();
+        foreign[4] = VerifyingKey::from(&SigningKey::random(&mut OsRng));
+        assert!(super::group_key(&foreign, 3).is_err());
+    }
+}
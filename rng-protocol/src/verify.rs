@@ -0,0 +1,85 @@
+//! End-to-end checks of a round's signed result.
+use k256::ecdsa::VerifyingKey;
+
+use crate::{derive_number, hmac_seed, seed_input, vrf, RngResult, Signed};
+
+/// How [verify] checked a result's seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedCheck {
+    /// The VRF proof is valid under the signing key.
+    Vrf,
+    /// The seed was recomputed from the HMAC secret.
+    Hmac,
+    /// HMAC mode without the secret, so the seed was taken on trust.
+    Unchecked,
+}
+
+/// A result that passed [verify].
+#[derive(Debug, Clone)]
+pub struct Verified {
+    pub result: RngResult,
+    pub signer: VerifyingKey,
+    pub seed: SeedCheck,
+}
+
+/// Check a round's signed result.
+///
+/// The result must be signed by one of `public_keys` and match its
+/// canonical signed payload. Its seed must follow from the round, through
+/// the VRF proof or by recomputing the HMAC if `hmac_key` is given, and its
+/// number from the seed.
+pub fn verify(
+    response: &Signed<RngResult>,
+    public_keys: &[VerifyingKey],
+    hmac_key: Option<&[u8]>,
+) -> anyhow::Result<Verified> {
+    let signer = response.signer()?;
+    anyhow::ensure!(
+        public_keys.contains(&signer),
+        "Signed by {}, which is not an expected public key",
+        hex::encode(signer.to_sec1_bytes())
+    );
+    let result = response.payload()?;
+
+    let (derived, check) = match (&result.proof, hmac_key) {
+        (Some(proof), _) => {
+            let alpha = seed_input(result.stream.as_deref(), result.timestamp)?;
+            let output = vrf::verify(&signer, &alpha, &hex::decode(proof)?)?;
+            (Some(output), SeedCheck::Vrf)
+        }
+        (None, Some(hmac_key)) => {
+            let seed = hmac_seed(hmac_key, result.stream.as_deref(), result.timestamp)?;
+            (Some(seed), SeedCheck::Hmac)
+        }
+        (None, None) => (None, SeedCheck::Unchecked),
+    };
+    let seed = match (derived, &result.seed) {
+        (Some(derived), Some(signed)) => {
+            anyhow::ensure!(
+                *signed == hex::encode(derived),
+                "Seed does not match the derived seed"
+            );
+            Some(derived)
+        }
+        (Some(derived), None) => Some(derived),
+        (None, Some(signed)) => Some(
+            hex::decode(signed)?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Seed is not 32 bytes"))?,
+        ),
+        // Results from before seeds were included can only be checked by signature.
+        (None, None) => None,
+    };
+    if let Some(seed) = seed {
+        let number = derive_number(&seed, result.range()?);
+        anyhow::ensure!(
+            number == result.number,
+            "Number does not match the derived number {number}"
+        );
+    }
+    Ok(Verified {
+        result,
+        signer,
+        seed: check,
+    })
+}
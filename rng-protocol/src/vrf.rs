@@ -0,0 +1,223 @@
+//! ECVRF over secp256k1, following the construction in RFC 9381.
+//!
+//! RFC 9381 only defines suites for P-256 and edwards25519. We use the same
+//! construction with secp256k1, SHA-256 and try-and-increment hashing to the
+//! curve, which is the widely deployed ECVRF-SECP256K1-SHA256-TAI suite. This
+//! lets us reuse the ECDSA signing key as the VRF key.
+use anyhow::{Context, Result};
+use k256::ecdsa::{SigningKey, VerifyingKey};
+use k256::elliptic_curve::bigint::ArrayEncoding;
+use k256::elliptic_curve::ops::Reduce;
+use k256::elliptic_curve::sec1::{FromEncodedPoint, ToEncodedPoint};
+use k256::elliptic_curve::{Curve, PrimeField};
+use k256::sha2::{Digest, Sha256};
+use k256::{AffinePoint, EncodedPoint, FieldBytes, ProjectivePoint, Scalar, Secp256k1, U256};
+
+const SUITE_STRING: u8 = 0xFE;
+/// Length of the challenge in bytes.
+const C_LEN: usize = 16;
+/// Length of a compressed point in bytes.
+const POINT_LEN: usize = 33;
+/// Length of an encoded proof: Gamma, c and s.
+pub const PROOF_LEN: usize = POINT_LEN + C_LEN + 32;
+
+/// Generate a proof for the given input, returning the proof and the VRF output.
+pub fn prove(signing_key: &SigningKey, alpha: &[u8]) -> Result<([u8; PROOF_LEN], [u8; 32])> {
+    let x: Scalar = *signing_key.as_nonzero_scalar().as_ref();
+    let h = encode_to_curve(&(ProjectivePoint::GENERATOR * x), alpha)?;
+    let (proof, gamma) = prove_point(x, h)?;
+    Ok((proof, proof_to_hash(&gamma)?))
+}
+
+/// Prove that `gamma = h * x` for the public key `G * x`, returning the proof and gamma.
+pub(crate) fn prove_point(
+    x: Scalar,
+    h: ProjectivePoint,
+) -> Result<([u8; PROOF_LEN], ProjectivePoint)> {
+    let y = ProjectivePoint::GENERATOR * x;
+    let gamma = h * x;
+    let k = nonce(&x, &point_to_bytes(&h)?);
+    let c = challenge(&[y, h, gamma, ProjectivePoint::GENERATOR * k, h * k])?;
+    let s = k + c_to_scalar(&c) * x;
+
+    let mut proof = [0u8; PROOF_LEN];
+    proof[..POINT_LEN].copy_from_slice(&point_to_bytes(&gamma)?);
+    proof[POINT_LEN..POINT_LEN + C_LEN].copy_from_slice(&c);
+    proof[POINT_LEN + C_LEN..].copy_from_slice(&s.to_bytes());
+    Ok((proof, gamma))
+}
+
+/// Verify a proof for the given input, returning the VRF output.
+pub fn verify(public_key: &VerifyingKey, alpha: &[u8], proof: &[u8]) -> Result<[u8; 32]> {
+    let y = ProjectivePoint::from(*public_key.as_affine());
+    let gamma = verify_point(y, encode_to_curve(&y, alpha)?, proof)?;
+    proof_to_hash(&gamma)
+}
+
+/// Check a proof that `gamma = h * x` for the public key `y = G * x`, returning gamma.
+pub(crate) fn verify_point(
+    y: ProjectivePoint,
+    h: ProjectivePoint,
+    proof: &[u8],
+) -> Result<ProjectivePoint> {
+    anyhow::ensure!(
+        proof.len() == PROOF_LEN,
+        "Invalid VRF proof length {}",
+        proof.len()
+    );
+    let gamma = point_from_bytes(&proof[..POINT_LEN]).context("Invalid Gamma in VRF proof")?;
+    let c: [u8; C_LEN] = proof[POINT_LEN..POINT_LEN + C_LEN].try_into()?;
+    let s = Option::<Scalar>::from(Scalar::from_repr(*FieldBytes::from_slice(
+        &proof[POINT_LEN + C_LEN..],
+    )))
+    .context("Invalid s in VRF proof")?;
+
+    let c_scalar = c_to_scalar(&c);
+    let u = ProjectivePoint::GENERATOR * s - y * c_scalar;
+    let v = h * s - gamma * c_scalar;
+    anyhow::ensure!(
+        challenge(&[y, h, gamma, u, v])? == c,
+        "VRF proof verification failed"
+    );
+    Ok(gamma)
+}
+
+/// Hash the input to a curve point using try-and-increment.
+pub(crate) fn encode_to_curve(y: &ProjectivePoint, alpha: &[u8]) -> Result<ProjectivePoint> {
+    let pk_string = point_to_bytes(y)?;
+    for ctr in 0..=u8::MAX {
+        let hash = Sha256::new()
+            .chain_update([SUITE_STRING, 0x01])
+            .chain_update(pk_string)
+            .chain_update(alpha)
+            .chain_update([ctr, 0x00])
+            .finalize();
+        let mut candidate = [0x02; POINT_LEN];
+        candidate[1..].copy_from_slice(&hash);
+        if let Some(point) = point_from_bytes(&candidate) {
+            return Ok(point);
+        }
+    }
+    Err(anyhow::anyhow!("Failed to hash VRF input to the curve"))
+}
+
+/// Deterministic nonce generation from RFC 6979, as required by RFC 9381 section 5.4.2.1.
+fn nonce(x: &Scalar, h_string: &[u8]) -> Scalar {
+    let h1 = <Scalar as Reduce<U256>>::reduce_bytes(&Sha256::digest(h_string));
+    let k = rfc6979::generate_k::<Sha256, _>(
+        &x.to_bytes(),
+        &Secp256k1::ORDER.to_be_byte_array(),
+        &h1.to_bytes(),
+        &[],
+    );
+    // generate_k only returns values in the range [1, n)
+    Option::from(Scalar::from_repr(k)).expect("RFC 6979 nonce out of range")
+}
+
+fn challenge(points: &[ProjectivePoint; 5]) -> Result<[u8; C_LEN]> {
+    let mut hasher = Sha256::new().chain_update([SUITE_STRING, 0x02]);
+    for point in points {
+        hasher.update(point_to_bytes(point)?);
+    }
+    let hash = hasher.chain_update([0x00]).finalize();
+    Ok(hash[..C_LEN].try_into()?)
+}
+
+pub(crate) fn proof_to_hash(gamma: &ProjectivePoint) -> Result<[u8; 32]> {
+    // secp256k1 has cofactor 1, so no cofactor clearing is needed.
+    Ok(Sha256::new()
+        .chain_update([SUITE_STRING, 0x03])
+        .chain_update(point_to_bytes(gamma)?)
+        .chain_update([0x00])
+        .finalize()
+        .into())
+}
+
+fn c_to_scalar(c: &[u8; C_LEN]) -> Scalar {
+    let mut bytes = FieldBytes::default();
+    bytes[32 - C_LEN..].copy_from_slice(c);
+    // Always less than the group order, so this never reduces.
+    <Scalar as Reduce<U256>>::reduce_bytes(&bytes)
+}
+
+fn point_to_bytes(point: &ProjectivePoint) -> Result<[u8; POINT_LEN]> {
+    point
+        .to_affine()
+        .to_encoded_point(true)
+        .as_bytes()
+        .try_into()
+        .context("Cannot encode the identity point")
+}
+
+fn point_from_bytes(bytes: &[u8]) -> Option<ProjectivePoint> {
+    let encoded = EncodedPoint::from_bytes(bytes).ok()?;
+    Option::<AffinePoint>::from(AffinePoint::from_encoded_point(&encoded)).map(Into::into)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(
+            &hex::decode("c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721")
+                .unwrap(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let key = signing_key();
+        for alpha in [&b""[..], b"sample", &[0xff; 100]] {
+            let (proof, output) = prove(&key, alpha).unwrap();
+            assert_eq!(verify(key.verifying_key(), alpha, &proof).unwrap(), output);
+        }
+    }
+
+    #[test]
+    fn wrong_alpha_is_rejected() {
+        let key = signing_key();
+        let (proof, _) = prove(&key, b"sample").unwrap();
+        assert!(verify(key.verifying_key(), b"sample2", &proof).is_err());
+    }
+
+    #[test]
+    fn tampered_proof_is_rejected() {
+        let key = signing_key();
+        let (proof, _) = prove(&key, b"sample").unwrap();
+        for i in [
+            0,
+            POINT_LEN - 1,
+            POINT_LEN,
+            POINT_LEN + C_LEN,
+            PROOF_LEN - 1,
+        ] {
+            let mut tampered = proof;
+            tampered[i] ^= 1;
+            assert!(
+                verify(key.verifying_key(), b"sample", &tampered).is_err(),
+                "byte {i}"
+            );
+        }
+        assert!(verify(key.verifying_key(), b"sample", &proof[1..]).is_err());
+        let other = SigningKey::from_slice(&[7; 32]).unwrap();
+        assert!(verify(other.verifying_key(), b"sample", &proof).is_err());
+    }
+
+    /// Published outputs must stay verifiable, so the encoding may never change.
+    #[test]
+    fn test_vector() {
+        let (proof, output) = prove(&signing_key(), b"sample").unwrap();
+        assert_eq!(
+            hex::encode(proof),
+            "0338ec99b5d0f94ebcc2c704c04af3de8b4289df8798e5fb9f920d7f5d77ac03d7\
+             718b9677d1c9348649ac2ec4f7ecbe519b30dd10c4eb5efc21dd5944709f2f3b7e\
+             97a25f6f095334593502d05103bc5b"
+        );
+        assert_eq!(
+            hex::encode(output),
+            "d466c22e14dc3b7fd169668dd3ee9ac6351429a24aebc5e8af61a0f0de89b65a"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+//! Append-only log of every result the server has issued.
+//!
+//! Each distinct signed result is logged once per UTC day as a JSON line.
+//! The leaves of a day form a Merkle tree, and once the day is over its
+//! root is signed and kept in a second file next to the log. Comparing the
+//! logs of different days lets anyone show the server never gave two
+//! different answers for one round.
+//!
+//! Leaf hashes are `SHA-256(0x00 || issued_at || signature || serialized)`
+//! with `issued_at` as 8 big-endian bytes and the raw signature bytes.
+//! Inner nodes are `SHA-256(0x01 || left || right)`, an odd node at the end
+//! of a level moves up unchanged, and the root of an empty day is the
+//! SHA-256 of no input.
+//!
+//! File I/O here is blocking, callers on the async runtime should go
+//! through `spawn_blocking`.
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use k256::sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// A single issued result.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Leaf {
+    pub(crate) day: u64,
+    /// Unix time the result was first issued, in seconds.
+    pub(crate) issued_at: u64,
+    pub(crate) timestamp: u64,
+    pub(crate) serialized: String,
+    pub(crate) signature: String,
+    pub(crate) recovery_id: u8,
+}
+
+/// What gets signed for each completed day.
+#[derive(Serialize)]
+pub(crate) struct RootPayload {
+    pub(crate) day: u64,
+    pub(crate) leaves: u64,
+    /// Hex-encoded Merkle root of the day's leaves.
+    pub(crate) root: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct SignedRoot {
+    pub(crate) day: u64,
+    pub(crate) leaves: u64,
+    pub(crate) root: String,
+    pub(crate) signature: String,
+    pub(crate) recovery_id: u8,
+    pub(crate) serialized: String,
+}
+
+pub(crate) struct AuditLog {
+    path: PathBuf,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    log: File,
+    /// Length of the log, up to the end of its last complete line.
+    log_len: u64,
+    /// Byte range of the log holding each day's leaves.
+    days: BTreeMap<u64, Range<u64>>,
+    roots_file: File,
+    /// Day that `seen` covers.
+    day: u64,
+    /// Rounds and signatures already logged today.
+    seen: HashSet<(u64, String)>,
+    roots: BTreeMap<u64, SignedRoot>,
+}
+
+impl AuditLog {
+    /// Open the log at the given path, creating it if needed.
+    ///
+    /// Signed roots are kept in the same path with a `.roots` suffix.
+    pub(crate) fn open(path: &Path) -> anyhow::Result<Self> {
+        let today = current_day()?;
+        let mut log_len = 0;
+        let mut days = BTreeMap::<u64, Range<u64>>::new();
+        let mut seen = HashSet::new();
+        for (leaf, range) in read_lines::<Leaf>(path)? {
+            log_len = range.end;
+            days.entry(leaf.day)
+                .and_modify(|day| day.end = range.end)
+                .or_insert(range);
+            if leaf.day == today {
+                seen.insert((leaf.timestamp, leaf.signature));
+            }
+        }
+        let roots_path = roots_path(path);
+        let roots = read_lines::<SignedRoot>(&roots_path)?
+            .into_iter()
+            .map(|(root, _)| (root.day, root))
+            .collect();
+        let open = |path: &Path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Unable to open {}", path.display()))
+        };
+        Ok(AuditLog {
+            path: path.to_owned(),
+            inner: Mutex::new(Inner {
+                log: open(path)?,
+                log_len,
+                days,
+                roots_file: open(&roots_path)?,
+                day: today,
+                seen,
+                roots,
+            }),
+        })
+    }
+
+    /// Log a signed result, unless it was already logged today.
+    pub(crate) fn record(
+        &self,
+        timestamp: u64,
+        serialized: &str,
+        signature: &str,
+        recovery_id: u8,
+    ) -> anyhow::Result<()> {
+        let mut inner = self.lock()?;
+        // Only check the time while holding the lock, so nothing gets
+        // logged for a day after its root was computed.
+        let issued_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let day = issued_at / SECS_PER_DAY;
+        if inner.day != day {
+            inner.day = day;
+            inner.seen.clear();
+        }
+        if !inner.seen.insert((timestamp, signature.to_owned())) {
+            return Ok(());
+        }
+        let leaf = Leaf {
+            day,
+            issued_at,
+            timestamp,
+            serialized: serialized.to_owned(),
+            signature: signature.to_owned(),
+            recovery_id,
+        };
+        let mut line = serde_json::to_vec(&leaf)?;
+        line.push(b'\n');
+        let start = inner.log_len;
+        if let Err(e) = inner.log.write_all(&line) {
+            // Drop whatever part of the line made it, so the next one starts clean.
+            let _ = inner.log.set_len(start);
+            inner.seen.remove(&(timestamp, signature.to_owned()));
+            return Err(e.into());
+        }
+        let end = start + line.len() as u64;
+        inner.log_len = end;
+        inner
+            .days
+            .entry(day)
+            .and_modify(|range| range.end = end)
+            .or_insert(start..end);
+        Ok(())
+    }
+
+    /// All leaves logged on the given day, in order.
+    ///
+    /// Only reads that day's part of the log, and without holding the lock,
+    /// so it doesn't hold up [AuditLog::record].
+    pub(crate) fn leaves(&self, day: u64) -> anyhow::Result<Vec<Leaf>> {
+        let Some(range) = self.lock()?.days.get(&day).cloned() else {
+            return Ok(vec![]);
+        };
+        let mut file = File::open(&self.path)
+            .with_context(|| format!("Unable to open {}", self.path.display()))?;
+        file.seek(SeekFrom::Start(range.start))?;
+        // The log is append-only, so everything up to the end of the range
+        // is already written and stays as it is.
+        let mut leaves = BufReader::new(file.take(range.end - range.start))
+            .lines()
+            .map(|line| Ok(serde_json::from_str::<Leaf>(&line?)?))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        // The clock going back across midnight can mix in another day's leaves.
+        leaves.retain(|leaf| leaf.day == day);
+        Ok(leaves)
+    }
+
+    pub(crate) fn signed_root(&self, day: u64) -> anyhow::Result<Option<SignedRoot>> {
+        Ok(self.lock()?.roots.get(&day).cloned())
+    }
+
+    /// Keep the signed root for a day, returning the one already kept if any.
+    pub(crate) fn store_root(&self, root: SignedRoot) -> anyhow::Result<SignedRoot> {
+        let mut inner = self.lock()?;
+        if let Some(existing) = inner.roots.get(&root.day) {
+            return Ok(existing.clone());
+        }
+        let mut line = serde_json::to_vec(&root)?;
+        line.push(b'\n');
+        inner.roots_file.write_all(&line)?;
+        inner.roots.insert(root.day, root.clone());
+        Ok(root)
+    }
+
+    fn lock(&self) -> anyhow::Result<std::sync::MutexGuard<'_, Inner>> {
+        self.inner
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Audit log lock poisoned"))
+    }
+}
+
+/// The UTC day that's currently in progress, counted from the epoch.
+pub(crate) fn current_day() -> anyhow::Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() / SECS_PER_DAY)
+}
+
+/// When the given day starts.
+pub(crate) fn day_start(day: u64) -> SystemTime {
+    UNIX_EPOCH + std::time::Duration::from_secs(day * SECS_PER_DAY)
+}
+
+fn leaf_hash(leaf: &Leaf) -> anyhow::Result<[u8; 32]> {
+    Ok(Sha256::new()
+        .chain_update([0x00])
+        .chain_update(leaf.issued_at.to_be_bytes())
+        .chain_update(hex::decode(&leaf.signature)?)
+        .chain_update(&leaf.serialized)
+        .finalize()
+        .into())
+}
+
+pub(crate) fn merkle_root(leaves: &[Leaf]) -> anyhow::Result<[u8; 32]> {
+    Ok(rng_protocol::merkle_root(
+        leaves.iter().map(leaf_hash).collect::<Result<_, _>>()?,
+    ))
+}
+
+fn roots_path(path: &Path) -> PathBuf {
+    let mut roots_path = path.as_os_str().to_owned();
+    roots_path.push(".roots");
+    roots_path.into()
+}
+
+/// Parse a file of JSON lines along with the byte range of each, treating
+/// a missing file as empty.
+///
+/// A final line without a newline was torn by a crash part way through
+/// writing it. It never made it into a response, so we cut it off the file.
+fn read_lines<T: for<'de> Deserialize<'de>>(path: &Path) -> anyhow::Result<Vec<(T, Range<u64>)>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e).with_context(|| format!("Unable to open {}", path.display())),
+    };
+    let mut reader = BufReader::new(file);
+    let mut entries = vec![];
+    let mut start = 0;
+    let mut line = vec![];
+    loop {
+        line.clear();
+        let len = reader
+            .read_until(b'\n', &mut line)
+            .with_context(|| format!("Unable to read {}", path.display()))?;
+        if len == 0 {
+            break;
+        }
+        if line.last() != Some(&b'\n') {
+            eprintln!(
+                "Dropping torn last line of {} at byte {start}",
+                path.display()
+            );
+            OpenOptions::new()
+                .write(true)
+                .open(path)
+                .and_then(|file| file.set_len(start))
+                .with_context(|| format!("Unable to truncate {}", path.display()))?;
+            break;
+        }
+        let end = start + len as u64;
+        let entry = serde_json::from_slice(&line)
+            .with_context(|| format!("Invalid entry in {} at byte {start}", path.display()))?;
+        entries.push((entry, start..end));
+        start = end;
+    }
+    Ok(entries)
+}
@@ -0,0 +1,67 @@
+//! JSON error responses, see [RngError].
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use rng_protocol::{RngError, ROUND_NOT_READY};
+
+/// An error response with a machine-readable code.
+pub(crate) struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    /// Seconds until the request is worth retrying, sent as `Retry-After`.
+    retry_after: Option<u64>,
+}
+
+impl ApiError {
+    /// The round hasn't started yet, and will in `retry_after` seconds.
+    pub(crate) fn round_not_ready(timestamp: u64, retry_after: u64) -> Self {
+        ApiError {
+            status: StatusCode::TOO_EARLY,
+            code: ROUND_NOT_READY,
+            message: format!("Round {timestamp} starts in {retry_after}s"),
+            retry_after: Some(retry_after),
+        }
+    }
+
+    /// The server can't currently sign, see `/readyz`.
+    pub(crate) fn not_ready(message: String) -> Self {
+        ApiError {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            code: "not_ready",
+            message,
+            retry_after: None,
+        }
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let code = match status {
+            StatusCode::BAD_REQUEST => "bad_request",
+            StatusCode::NOT_FOUND => "not_found",
+            _ => "internal_error",
+        };
+        ApiError {
+            status,
+            code,
+            message: status.canonical_reason().unwrap_or_default().to_owned(),
+            retry_after: None,
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(RngError {
+            code: self.code.to_owned(),
+            message: self.message,
+        });
+        match self.retry_after {
+            Some(secs) => {
+                (self.status, [(header::RETRY_AFTER, secs.to_string())], body).into_response()
+            }
+            None => (self.status, body).into_response(),
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Signing keys and the rounds they are valid for.
+use std::str::FromStr;
+use std::sync::Arc;
+
+use k256::ecdsa::SigningKey;
+use zeroize::Zeroizing;
+
+use crate::signer::Signer;
+
+/// A signing key along with its validity window, in rounds since the epoch.
+///
+/// `valid_from` is inclusive and `valid_until` is exclusive. A missing
+/// bound means the window is open on that side.
+#[derive(Clone)]
+pub(crate) struct RngKey {
+    pub(crate) signer: Arc<dyn Signer>,
+    pub(crate) valid_from: Option<u64>,
+    pub(crate) valid_until: Option<u64>,
+}
+
+impl RngKey {
+    fn is_valid_at(&self, timestamp: u64) -> bool {
+        self.valid_from.is_none_or(|from| from <= timestamp)
+            && self.valid_until.is_none_or(|until| timestamp < until)
+    }
+}
+
+/// Parses `HEX` or `HEX@FROM-UNTIL`, where either bound may be left empty.
+impl FromStr for RngKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (key, window) = match s.split_once('@') {
+            Some((key, window)) => (key, Some(window)),
+            None => (s, None),
+        };
+        let key_bytes = Zeroizing::new(hex::decode(key)?);
+        let signing_key = SigningKey::from_bytes(key_bytes.as_slice().into())?;
+        let (valid_from, valid_until) = match window {
+            None => (None, None),
+            Some(window) => {
+                let (from, until) = window.split_once('-').ok_or_else(|| {
+                    anyhow::anyhow!("Invalid key validity window {window}, expected FROM-UNTIL")
+                })?;
+                let parse = |bound: &str| -> anyhow::Result<Option<u64>> {
+                    Ok(if bound.is_empty() {
+                        None
+                    } else {
+                        Some(bound.parse()?)
+                    })
+                };
+                (parse(from)?, parse(until)?)
+            }
+        };
+        if let (Some(from), Some(until)) = (valid_from, valid_until) {
+            anyhow::ensure!(from < until, "Empty key validity window {from}-{until}");
+        }
+        Ok(RngKey {
+            signer: Arc::new(signing_key),
+            valid_from,
+            valid_until,
+        })
+    }
+}
+
+/// All signing keys, with at most one key valid for any round.
+#[derive(Clone)]
+pub(crate) struct KeyRing(Arc<Vec<RngKey>>);
+
+impl KeyRing {
+    pub(crate) fn new(mut keys: Vec<RngKey>) -> anyhow::Result<Self> {
+        anyhow::ensure!(!keys.is_empty(), "At least one signing key is required");
+        keys.sort_by_key(|key| key.valid_from.unwrap_or(0));
+        for pair in keys.windows(2) {
+            let [prev, next] = pair else { unreachable!() };
+            let disjoint = prev
+                .valid_until
+                .zip(next.valid_from)
+                .is_some_and(|(until, from)| until <= from);
+            anyhow::ensure!(
+                disjoint,
+                "Signing key validity windows overlap: {:?}-{:?} and {:?}-{:?}",
+                prev.valid_from,
+                prev.valid_until,
+                next.valid_from,
+                next.valid_until
+            );
+        }
+        Ok(KeyRing(Arc::new(keys)))
+    }
+
+    /// The signer valid for the given round, if any.
+    pub(crate) fn for_round(&self, timestamp: u64) -> Option<&dyn Signer> {
+        self.0
+            .iter()
+            .find(|key| key.is_valid_at(timestamp))
+            .map(|key| &*key.signer)
+    }
+
+    /// Whether all keys are held in process, see [Signer::signing_key].
+    pub(crate) fn in_process(&self) -> bool {
+        self.0.iter().all(|key| key.signer.signing_key().is_some())
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &RngKey> {
+        self.0.iter()
+    }
+}
@@ -0,0 +1,177 @@
+//! Password-encrypted storage for the server's secrets.
+//!
+//! The keystore is a JSON file holding the secrets encrypted with
+//! ChaCha20-Poly1305, under a key derived from a password with
+//! PBKDF2-HMAC-SHA256. The password itself never goes on the command line
+//! or in the environment, where it would show up in the process list and
+//! in container task definitions: it's read from a file, an inherited file
+//! descriptor or stdin.
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+use chacha20poly1305::aead::Aead;
+use chacha20poly1305::{ChaCha20Poly1305, Key, KeyInit, Nonce};
+use hmac::Hmac;
+use k256::sha2::Sha256;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+const VERSION: u32 = 1;
+/// OWASP's recommendation for PBKDF2-HMAC-SHA256.
+const KDF_ROUNDS: u32 = 600_000;
+/// Bounds on the rounds a keystore may ask for, so a tampered or corrupt
+/// file can neither skip key stretching nor hang startup.
+const MIN_KDF_ROUNDS: u32 = 100_000;
+const MAX_KDF_ROUNDS: u32 = 10_000_000;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// The secrets kept in a keystore, hex encoded like on the command line.
+#[derive(Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub(crate) struct Secrets {
+    /// Signing keys, in the same `HEX@FROM-UNTIL` syntax as `--signing-key`.
+    pub(crate) signing_keys: Vec<String>,
+    pub(crate) hmac_secret: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Keystore {
+    version: u32,
+    kdf_rounds: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Where to read a keystore or its password from: a path, `fd:N` or `-` for stdin.
+#[derive(Clone, Debug)]
+pub(crate) enum SecretSource {
+    Path(PathBuf),
+    Fd(u32),
+    Stdin,
+}
+
+impl FromStr for SecretSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s {
+            "-" => SecretSource::Stdin,
+            _ => match s.strip_prefix("fd:") {
+                Some(fd) => SecretSource::Fd(fd.parse()?),
+                None => SecretSource::Path(s.into()),
+            },
+        })
+    }
+}
+
+impl SecretSource {
+    /// Read the whole secret, naming it `what` in errors.
+    pub(crate) fn read(&self, what: &str) -> anyhow::Result<String> {
+        match self {
+            SecretSource::Path(path) => std::fs::read_to_string(path)
+                .with_context(|| format!("Unable to read {what} {}", path.display())),
+            SecretSource::Fd(fd) => std::fs::read_to_string(format!("/dev/fd/{fd}"))
+                .with_context(|| format!("Unable to read {what} from file descriptor {fd}")),
+            SecretSource::Stdin => std::io::read_to_string(std::io::stdin())
+                .with_context(|| format!("Unable to read {what} from stdin")),
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub(crate) struct PasswordOpts {
+    /// Where to read the keystore password from: a file, `fd:N` for an
+    /// inherited file descriptor, or `-` for stdin.
+    ///
+    /// Required with a keystore. There's deliberately no way to pass the
+    /// password itself on the command line or in the environment.
+    #[clap(long, env = "RARE_EVO_KEYSTORE_PASSWORD_FILE")]
+    keystore_password_file: Option<SecretSource>,
+}
+
+impl PasswordOpts {
+    pub(crate) fn password(&self) -> anyhow::Result<Zeroizing<String>> {
+        let mut password = Zeroizing::new(
+            self.keystore_password_file
+                .as_ref()
+                .context("Keystore password needed, pass --keystore-password-file")?
+                .read("keystore password")?,
+        );
+        // Files usually end with a newline that isn't part of the password.
+        let len = password.trim_end_matches(['\r', '\n']).len();
+        password.truncate(len);
+        anyhow::ensure!(!password.is_empty(), "Keystore password must not be empty");
+        Ok(password)
+    }
+}
+
+/// Encrypt the secrets under the given password, returning the keystore's JSON.
+pub(crate) fn encrypt(secrets: &Secrets, password: &str) -> anyhow::Result<String> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let plaintext = Zeroizing::new(serde_json::to_vec(secrets)?);
+    let ciphertext = cipher(password, &salt, KDF_ROUNDS)
+        .encrypt(Nonce::from_slice(&nonce), plaintext.as_slice())
+        .map_err(|_| anyhow::anyhow!("Unable to encrypt keystore"))?;
+    Ok(serde_json::to_string_pretty(&Keystore {
+        version: VERSION,
+        kdf_rounds: KDF_ROUNDS,
+        salt: hex::encode(salt),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    })?)
+}
+
+/// Write a new keystore file, readable only by the current user.
+///
+/// Fails if the file already exists.
+pub(crate) fn write_new(path: &Path, contents: &str) -> anyhow::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    std::io::Write::write_all(
+        &mut options
+            .open(path)
+            .with_context(|| format!("Unable to create {}", path.display()))?,
+        contents.as_bytes(),
+    )?;
+    Ok(())
+}
+
+/// Decrypt a keystore's JSON with the given password.
+pub(crate) fn decrypt(keystore: &str, password: &str) -> anyhow::Result<Secrets> {
+    let keystore: Keystore = serde_json::from_str(keystore).context("Invalid keystore")?;
+    anyhow::ensure!(
+        keystore.version == VERSION,
+        "Unsupported keystore version {}",
+        keystore.version
+    );
+    anyhow::ensure!(
+        (MIN_KDF_ROUNDS..=MAX_KDF_ROUNDS).contains(&keystore.kdf_rounds),
+        "Keystore asks for {} KDF rounds, expected {MIN_KDF_ROUNDS} to {MAX_KDF_ROUNDS}",
+        keystore.kdf_rounds
+    );
+    let nonce = hex::decode(&keystore.nonce)?;
+    anyhow::ensure!(nonce.len() == NONCE_LEN, "Invalid keystore nonce");
+    let plaintext = Zeroizing::new(
+        cipher(password, &hex::decode(&keystore.salt)?, keystore.kdf_rounds)
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                hex::decode(&keystore.ciphertext)?.as_slice(),
+            )
+            .map_err(|_| anyhow::anyhow!("Unable to decrypt keystore, wrong password?"))?,
+    );
+    Ok(serde_json::from_slice(&plaintext)?)
+}
+
+fn cipher(password: &str, salt: &[u8], rounds: u32) -> ChaCha20Poly1305 {
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(password.as_bytes(), salt, rounds, key.as_mut_slice());
+    ChaCha20Poly1305::new(Key::from_slice(key.as_slice()))
+}
@@ -1,14 +1,42 @@
-use axum::extract::Path;
+mod audit;
+mod error;
+mod keys;
+mod keystore;
+mod metrics;
+mod signer;
+mod tls;
+mod verify;
+
+use anyhow::Context;
+use audit::AuditLog;
+use axum::extract::{Path, Query};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::IntoResponse;
 use axum::Json;
 use axum::{extract::State, routing::get, Router};
 use clap::{Parser, Subcommand};
-use hmac::{Hmac, Mac};
-use k256::ecdsa::SigningKey;
-use k256::sha2::Sha256;
+use error::ApiError;
+use futures_util::stream::{self, Stream, StreamExt};
+use k256::ecdsa::{SigningKey, VerifyingKey};
+use k256::sha2::{Digest, Sha256};
+use keys::{KeyRing, RngKey};
+use keystore::{PasswordOpts, SecretSource};
+use metrics::Metrics;
 use rand_core::{OsRng, RngCore};
-use serde::Serialize;
+use rng_protocol::{
+    vrf, BundleRoot, RngBundle, RngCommitment, RngKeyInfo, RngKeySet, RngReadiness, RngResult,
+    RngShare, Signed, MAX_RANGE,
+};
+use serde::{Deserialize, Serialize};
+use signer::{Signer, SignerOpts};
+use std::collections::BTreeMap;
 use std::net::SocketAddr;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tls::TlsOpts;
+use tokio::sync::{broadcast, watch};
+use zeroize::Zeroizing;
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -23,19 +51,399 @@ enum Commands {
     Serve {
         #[clap(long, env = "RARE_EVO_BIND", default_value = "[::]:3000")]
         bind: SocketAddr,
-        #[clap(long, env = "RARE_EVO_SIGNING_KEY")]
-        signing_key: String,
-        #[clap(long, env = "RARE_EVO_HMAC_SECRET")]
-        hmac_secret: String,
+        #[clap(flatten)]
+        rounds: RoundOpts,
+        /// Public key the server is expected to sign with, hex encoded.
+        ///
+        /// Refuses to start unless it's one of the signing keys, in case
+        /// the wrong key or signer was configured.
+        #[clap(
+            long = "public-key",
+            env = "RARE_EVO_PUBLIC_KEY",
+            value_parser = parse_public_key
+        )]
+        expected_public_key: Option<VerifyingKey>,
+        /// Serve rounds up to this long before they start by our clock,
+        /// in seconds or with an `s`, `m` or `h` suffix.
+        ///
+        /// Allows for clients whose clocks run slightly ahead of ours.
+        /// Anything served early is known before its round starts, so keep
+        /// this well below the time games stop taking bets for a round.
+        #[clap(
+            long,
+            env = "RARE_EVO_CLOCK_SKEW",
+            default_value = "0s",
+            value_parser = rng_protocol::parse_duration
+        )]
+        clock_skew: u64,
+        /// Log every issued result to this file, for auditing.
+        ///
+        /// Each day's log is summarized by a signed Merkle root once the
+        /// day is over, kept in the same path with a `.roots` suffix.
+        /// Both are served from `/audit/{day}`.
+        #[clap(long, env = "RARE_EVO_AUDIT_LOG")]
+        audit_log: Option<PathBuf>,
+        #[clap(flatten)]
+        tls: TlsOpts,
+        /// On SIGTERM or SIGINT, how long to wait for open requests to
+        /// finish before closing them, in seconds or with an `s`, `m` or
+        /// `h` suffix.
+        #[clap(
+            long,
+            env = "RARE_EVO_SHUTDOWN_TIMEOUT",
+            default_value = "20s",
+            value_parser = rng_protocol::parse_duration
+        )]
+        shutdown_timeout: u64,
+    },
+    /// Write the bundle for a UTC day, as served from `/bundle/{date}`
+    ///
+    /// Takes the same keys and settings as `serve`, and must match them
+    /// for the bundle to agree with what the server handed out.
+    ExportBundle {
+        #[clap(flatten)]
+        rounds: RoundOpts,
+        /// Day to export, as `YYYY-MM-DD`. It must be over.
+        #[clap(long)]
+        date: String,
+        /// File to write the bundle to.
+        #[clap(long)]
+        output: PathBuf,
     },
     /// Generate random keys
-    Generate {},
+    Generate {
+        /// Write the keys to a new password-encrypted keystore at this
+        /// path, instead of printing them.
+        #[clap(long)]
+        keystore: Option<PathBuf>,
+        #[clap(flatten)]
+        password: PasswordOpts,
+        /// Deal a group key as this many operator signing keys for
+        /// threshold mode, see `serve --share-group-key`, and print them.
+        ///
+        /// The group's secret key is never shown or stored.
+        #[clap(long, requires = "threshold", conflicts_with = "keystore")]
+        shares: Option<u8>,
+        /// Number of operators needed to settle a round, with `--shares`.
+        #[clap(long, requires = "shares")]
+        threshold: Option<u8>,
+        /// Store these signing keys in the keystore instead of a new random
+        /// one, in the same syntax as `serve --signing-key`.
+        ///
+        /// For moving existing keys into a keystore. Prefer the environment
+        /// variable over the flag, so the keys stay out of the process list.
+        #[clap(
+            long = "signing-key",
+            env = "RARE_EVO_SIGNING_KEY",
+            value_delimiter = ',',
+            requires_all = ["keystore", "hmac_secret"],
+            conflicts_with = "shares"
+        )]
+        signing_keys: Vec<String>,
+        /// HMAC secret to store along with `--signing-key`.
+        #[clap(long, env = "RARE_EVO_HMAC_SECRET", requires = "signing_keys")]
+        hmac_secret: Option<String>,
+    },
+    /// Add a new random signing key to a keystore, for rotating keys
+    ///
+    /// The new key is valid from round `--valid-from`, and the key valid
+    /// until then without an end is ended there. Restart the server with
+    /// the updated keystore well before that round, so clients can pick up
+    /// the new key from `/public-keys` while it is still signed by the old
+    /// one.
+    AddKey {
+        /// Keystore written by `generate`, updated in place.
+        #[clap(long, env = "RARE_EVO_KEYSTORE")]
+        keystore: PathBuf,
+        #[clap(flatten)]
+        password: PasswordOpts,
+        /// First round the new key is valid for.
+        #[clap(long)]
+        valid_from: u64,
+    },
+    /// Hold the signing key from a keystore in a separate process
+    ///
+    /// Signs for a server started with `--signer-socket`, so the key never
+    /// enters the server itself.
+    #[cfg(unix)]
+    Signer {
+        /// Unix socket to listen on.
+        #[clap(long, env = "RARE_EVO_SIGNER_SOCKET")]
+        socket: PathBuf,
+        /// Keystore written by `generate`, see `serve --keystore`.
+        #[clap(long, env = "RARE_EVO_KEYSTORE")]
+        keystore: SecretSource,
+        #[clap(flatten)]
+        password: PasswordOpts,
+        /// Which of the keystore's keys to sign with, by public key.
+        ///
+        /// Only needed when the keystore holds several keys. Run one
+        /// signer per key to rotate keys with external signers.
+        #[clap(long, value_parser = parse_public_key)]
+        public_key: Option<VerifyingKey>,
+    },
+    /// Check saved responses from `/number/{timestamp}`, `/numbers` or `/bundle/{date}`
+    ///
+    /// Recovers the signer and checks it's one of the given public keys,
+    /// and that the response matches its signed payload. VRF proofs are
+    /// always checked, HMAC-derived numbers only with `--hmac-secret`.
+    /// Bundles must also cover their whole day and match their signed
+    /// Merkle root.
+    Verify {
+        /// Expected public key, hex encoded. Pass several to accept any of them.
+        #[clap(
+            long = "public-key",
+            value_delimiter = ',',
+            required = true,
+            value_parser = parse_public_key
+        )]
+        public_keys: Vec<VerifyingKey>,
+        /// Recompute numbers from this secret, proving they were derived honestly.
+        #[clap(long, env = "RARE_EVO_HMAC_SECRET")]
+        hmac_secret: Option<String>,
+        /// File holding the saved response, or `-` for stdin.
+        input: Option<PathBuf>,
+    },
+}
+
+/// Keys and settings that determine every round's result.
+#[derive(clap::Args)]
+struct RoundOpts {
+    /// Signing key, optionally restricted to the rounds `FROM` (inclusive)
+    /// to `UNTIL` (exclusive) with the syntax `HEX@FROM-UNTIL`.
+    ///
+    /// Pass several keys to rotate between them. Their validity windows
+    /// must not overlap, either bound may be left empty. Add a new key
+    /// well before its window starts, so clients can pick it up from
+    /// `/public-keys` while it is still signed by the old key.
+    ///
+    /// Prefer `--keystore` or an external signer in production, keys
+    /// passed here show up in the process list.
+    #[clap(
+        long = "signing-key",
+        env = "RARE_EVO_SIGNING_KEY",
+        value_delimiter = ','
+    )]
+    signing_keys: Vec<RngKey>,
+    /// Secret used to derive numbers, not needed in VRF mode.
+    #[clap(
+        long,
+        env = "RARE_EVO_HMAC_SECRET",
+        required_unless_present_any = ["vrf", "keystore"]
+    )]
+    hmac_secret: Option<String>,
+    /// Load the signing keys and HMAC secret from a keystore written by
+    /// `generate`, instead of passing them in the clear.
+    ///
+    /// Either a path, `fd:N` for an inherited file descriptor, or `-`
+    /// for stdin.
+    #[clap(
+        long,
+        env = "RARE_EVO_KEYSTORE",
+        conflicts_with_all = ["signing_keys", "hmac_secret"]
+    )]
+    keystore: Option<SecretSource>,
+    #[clap(flatten)]
+    password: PasswordOpts,
+    #[clap(flatten)]
+    signer: SignerOpts,
+    /// Derive numbers with an ECVRF under the signing key instead of an HMAC.
+    ///
+    /// Every result then carries a proof that anyone holding the
+    /// public key can check, showing the number is the only valid
+    /// output for its timestamp.
+    #[clap(long, env = "RARE_EVO_VRF", conflicts_with = "hmac_secret")]
+    vrf: bool,
+    /// Length of each round, in seconds or with an `s`, `m` or `h` suffix.
+    ///
+    /// Round numbers in all requests and responses count periods of
+    /// this length since the epoch.
+    #[clap(
+        long,
+        env = "RARE_EVO_ROUND_PERIOD",
+        default_value = "1m",
+        value_parser = parse_round_period
+    )]
+    round_period: u64,
+    /// Enable beacon mode, chaining every round to its predecessor
+    /// starting from this round.
+    ///
+    /// Each payload then carries the hash of the previous round's
+    /// signature, so auditors can walk the chain back to this round.
+    #[clap(long, env = "RARE_EVO_BEACON_GENESIS")]
+    beacon_genesis: Option<u64>,
+    /// Serve `/share` as one operator of a threshold group with this
+    /// public key, as printed by `generate --shares`.
+    ///
+    /// The signing key must be this operator's share of the group key.
+    #[clap(
+        long,
+        env = "RARE_EVO_SHARE_GROUP_KEY",
+        value_parser = parse_public_key
+    )]
+    share_group_key: Option<VerifyingKey>,
+}
+
+impl RoundOpts {
+    /// Load the keys and set up the state for serving rounds.
+    fn into_state(self) -> anyhow::Result<AppState> {
+        let RoundOpts {
+            signing_keys,
+            hmac_secret,
+            keystore,
+            password,
+            signer,
+            vrf,
+            round_period,
+            beacon_genesis,
+            share_group_key,
+        } = self;
+        let (signing_keys, hmac_secret) = match keystore {
+            Some(source) => {
+                let secrets = keystore::decrypt(&source.read("keystore")?, &password.password()?)?;
+                (
+                    secrets
+                        .signing_keys
+                        .iter()
+                        .map(|key| key.parse())
+                        .collect::<anyhow::Result<_>>()?,
+                    Some(Zeroizing::new(secrets.hmac_secret.clone())),
+                )
+            }
+            None => (signing_keys, hmac_secret.map(Zeroizing::new)),
+        };
+        let keys = match signer.load()? {
+            Some(signer) => KeyRing::new(vec![RngKey {
+                signer,
+                valid_from: None,
+                valid_until: None,
+            }])?,
+            None => {
+                anyhow::ensure!(
+                    !signing_keys.is_empty(),
+                    "A signing key is required, pass --signing-key, --keystore \
+                     or an external signer"
+                );
+                KeyRing::new(signing_keys)?
+            }
+        };
+        anyhow::ensure!(
+            keys.in_process() || !(vrf || beacon_genesis.is_some() || share_group_key.is_some()),
+            "VRF, beacon and threshold share mode need the signing key in process"
+        );
+        let seed_source = if vrf {
+            println!("VRF mode enabled");
+            SeedSource::Vrf
+        } else {
+            let hmac_secret = hmac_secret
+                .ok_or_else(|| anyhow::anyhow!("An HMAC secret is required without --vrf"))?;
+            SeedSource::Hmac(Arc::new(Zeroizing::new(hex::decode(&*hmac_secret)?)))
+        };
+        println!("Rounds last {round_period} seconds");
+        let beacon = beacon_genesis.map(|genesis| {
+            println!("Beacon mode enabled, genesis round {genesis}");
+            Arc::new(Beacon {
+                genesis,
+                links: Mutex::new(vec![]),
+            })
+        });
+        if let Some(group_key) = &share_group_key {
+            println!(
+                "Serving threshold shares for group key {}",
+                hex::encode(group_key.to_sec1_bytes())
+            );
+        }
+        Ok(AppState {
+            round_period,
+            clock_skew: 0,
+            keys,
+            seed_source,
+            beacon,
+            share_group_key,
+            rounds: broadcast::channel(16).0,
+            audit: None,
+            bundles: Arc::new(BundleCache::default()),
+            metrics: Arc::new(Metrics::new()),
+            shutdown: Arc::new(watch::Sender::new(false)),
+        })
+    }
 }
 
 #[derive(Clone)]
 struct AppState {
-    signing_key: SigningKey,
-    hmac_template: Hmac<Sha256>,
+    /// Length of each round in seconds.
+    round_period: u64,
+    /// How many seconds early a round may be served, see [AppState::check_started].
+    clock_skew: u64,
+    keys: KeyRing,
+    seed_source: SeedSource,
+    beacon: Option<Arc<Beacon>>,
+    /// Group key this server holds a share of, if serving `/share`.
+    share_group_key: Option<VerifyingKey>,
+    /// Each round's result, published as soon as the round starts.
+    rounds: broadcast::Sender<Arc<Signed<RngResult>>>,
+    audit: Option<Arc<AuditLog>>,
+    bundles: Arc<BundleCache>,
+    metrics: Arc<Metrics>,
+    /// Set once the server starts shutting down, ending open `/stream` subscriptions.
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+/// How each round's seed is derived from its timestamp.
+#[derive(Clone)]
+enum SeedSource {
+    /// HMAC under a secret separate from the signing key.
+    ///
+    /// We keep the raw key rather than an HMAC instance, so it's zeroized on drop.
+    Hmac(Arc<Zeroizing<Vec<u8>>>),
+    /// ECVRF output under the signing key, see [vrf].
+    Vrf,
+}
+
+/// Hash-chained beacon, linking each round to the one before it.
+///
+/// ECDSA signing is deterministic (RFC 6979), so the chain can always be
+/// recomputed from the genesis round. We cache the signature hash of every
+/// round we've seen so each new round only costs a single signature.
+struct Beacon {
+    genesis: u64,
+    links: Mutex<Vec<[u8; 32]>>,
+}
+
+/// Most days of bundles kept in [BundleCache].
+const MAX_CACHED_BUNDLES: usize = 7;
+
+/// Serialized bundles of finished days, which never change once generated.
+///
+/// Keeps the latest [MAX_CACHED_BUNDLES] days requested. Bundles are
+/// generated one at a time, so requests for uncached days can't tie up
+/// more than a single blocking thread between them.
+#[derive(Default)]
+struct BundleCache {
+    days: Mutex<BTreeMap<u64, axum::body::Bytes>>,
+    generating: tokio::sync::Mutex<()>,
+}
+
+impl BundleCache {
+    fn get(&self, day: u64) -> Result<Option<axum::body::Bytes>, axum::http::StatusCode> {
+        let days = self
+            .days
+            .lock()
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        Ok(days.get(&day).cloned())
+    }
+
+    fn insert(&self, day: u64, body: axum::body::Bytes) -> Result<(), axum::http::StatusCode> {
+        let mut days = self
+            .days
+            .lock()
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        days.insert(day, body);
+        while days.len() > MAX_CACHED_BUNDLES {
+            days.pop_first();
+        }
+        Ok(())
+    }
 }
 
 #[tokio::main]
@@ -45,104 +453,1016 @@ async fn main() -> anyhow::Result<()> {
     match cli.command {
         Commands::Serve {
             bind,
-            signing_key,
-            hmac_secret,
+            rounds,
+            expected_public_key,
+            clock_skew,
+            audit_log,
+            tls,
+            shutdown_timeout,
         } => {
             println!("Starting server on {bind}");
-            let key_bytes = hex::decode(signing_key)?;
-            let signing_key = SigningKey::from_bytes(key_bytes.as_slice().into())?;
-            let hmac_key = hex::decode(hmac_secret)?;
-            let hmac_template = Hmac::<Sha256>::new_from_slice(&hmac_key)
-                .map_err(|_| anyhow::anyhow!("Failed to create HMAC instance from secret"))?;
-            let state = AppState {
-                signing_key,
-                hmac_template,
-            };
+            let mut state = rounds.into_state()?;
+            anyhow::ensure!(
+                clock_skew < state.round_period,
+                "Clock skew tolerance must be shorter than a round"
+            );
+            state.clock_skew = clock_skew;
+            state.self_test(expected_public_key.as_ref())?;
+            if let Some(audit_log) = audit_log {
+                println!("Logging issued results to {}", audit_log.display());
+                state.audit = Some(Arc::new(AuditLog::open(&audit_log)?));
+                tokio::spawn(sign_audit_roots(state.clone()));
+            }
+            if let Some(beacon) = &state.beacon {
+                // Walk the chain up to the current round now, rather than on the first request.
+                let now = state.current_round()?;
+                if now >= beacon.genesis {
+                    state.signed_round(None, now, None).map_err(|status| {
+                        anyhow::anyhow!("Failed to compute beacon chain: {status}")
+                    })?;
+                }
+            }
+            tokio::spawn(publish_rounds(state.clone()));
             let app = Router::new()
                 .route("/number/{timestamp}", get(generate_signed_number))
+                .route("/numbers", get(generate_signed_numbers))
+                .route("/stream", get(stream_numbers))
+                .route("/share/{timestamp}", get(generate_share))
+                .route("/commitment/{timestamp}", get(generate_commitment))
+                .route(
+                    "/stream/{name}/number/{timestamp}",
+                    get(generate_signed_number),
+                )
+                .route("/stream/{name}/numbers", get(generate_signed_numbers))
+                .route("/stream/{name}/share/{timestamp}", get(generate_share))
+                .route(
+                    "/stream/{name}/commitment/{timestamp}",
+                    get(generate_commitment),
+                )
                 .route("/public-key", get(public_key))
+                .route("/public-keys", get(public_keys))
+                .route("/round-period", get(round_period_secs))
+                .route("/audit/{day}", get(audit_day))
+                .route("/bundle/{date}", get(bundle_day))
+                .route("/metrics", get(metrics::metrics))
                 .route("/healthz", get(health))
-                .with_state(state);
-            let listener = tokio::net::TcpListener::bind(bind).await?;
-            axum::serve(listener, app).await?;
+                .route("/readyz", get(ready))
+                .route_layer(axum::middleware::from_fn_with_state(
+                    state.clone(),
+                    metrics::track_requests,
+                ))
+                .with_state(state.clone());
+            let tls = tls.load().await?;
+            let handle = axum_server::Handle::new();
+            tokio::spawn(shutdown_on_signal(
+                state,
+                handle.clone(),
+                Duration::from_secs(shutdown_timeout),
+            ));
+            let app = app.into_make_service();
+            match tls {
+                Some(config) => {
+                    axum_server::bind_rustls(bind, config)
+                        .handle(handle)
+                        .serve(app)
+                        .await?
+                }
+                None => axum_server::bind(bind).handle(handle).serve(app).await?,
+            }
+            println!("Server stopped");
             Ok(())
         }
-        Commands::Generate {} => {
+        Commands::ExportBundle {
+            rounds,
+            date,
+            output,
+        } => {
+            let day = rng_protocol::parse_date(&date)?;
+            let state = rounds.into_state()?;
+            let last = *rng_protocol::day_rounds(day, state.round_period).end();
+            anyhow::ensure!(
+                state.current_round()? > last,
+                "{date} is not over yet, its last round is {last}"
+            );
+            let bundle = state
+                .bundle(day)
+                .map_err(|status| anyhow::anyhow!("Unable to build bundle: {status}"))?;
+            std::fs::write(&output, serde_json::to_vec(&bundle)?)
+                .map_err(|e| anyhow::anyhow!("Unable to write {}: {e}", output.display()))?;
+            eprintln!(
+                "Wrote {} results for {date} to {}",
+                bundle.results.len(),
+                output.display()
+            );
+            Ok(())
+        }
+        Commands::Generate {
+            keystore,
+            password,
+            shares,
+            threshold,
+            signing_keys,
+            hmac_secret,
+        } => {
             let mut rng = OsRng;
-            let key = SigningKey::random(&mut rng);
-            let key = hex::encode_upper(key.to_bytes());
+            if let Some((operators, threshold)) = shares.zip(threshold) {
+                let (group_key, shares) =
+                    rng_protocol::threshold::split(&mut rng, threshold, operators)?;
+                eprintln!(
+                    "Group public key: {}",
+                    hex::encode(group_key.to_sec1_bytes())
+                );
+                for (index, share) in (1..).zip(shares) {
+                    let key = Zeroizing::new(hex::encode_upper(share.to_bytes()));
+                    eprintln!(
+                        "Operator {index} signing key: {}, public key: {}",
+                        *key,
+                        hex::encode(share.verifying_key().to_sec1_bytes())
+                    );
+                }
+                return Ok(());
+            }
+            if let Some(path) = keystore.as_ref().filter(|_| !signing_keys.is_empty()) {
+                // Same checks as the server makes when loading them.
+                let keys = KeyRing::new(
+                    signing_keys
+                        .iter()
+                        .map(|key| key.parse())
+                        .collect::<anyhow::Result<_>>()?,
+                )?;
+                let secrets = keystore::Secrets {
+                    signing_keys,
+                    hmac_secret: hmac_secret
+                        .context("Impossible: --signing-key requires --hmac-secret")?,
+                };
+                keystore::write_new(path, &keystore::encrypt(&secrets, &password.password()?)?)?;
+                eprintln!("Wrote keystore to {}", path.display());
+                for key in keys.iter() {
+                    eprintln!(
+                        "Public key: {}",
+                        hex::encode(key.signer.verifying_key().to_sec1_bytes())
+                    );
+                }
+                return Ok(());
+            }
+            let signing_key = SigningKey::random(&mut rng);
+            let key = Zeroizing::new(hex::encode_upper(signing_key.to_bytes()));
             const KEY_SIZE: usize = 20;
-            let mut hmac_key = [0u8; KEY_SIZE];
-            rng.fill_bytes(&mut hmac_key);
-            let hmac_key = hex::encode_upper(hmac_key);
-            eprintln!("Signing key: {key}");
-            eprintln!("HMAC key: {hmac_key}");
+            let mut hmac_key = Zeroizing::new([0u8; KEY_SIZE]);
+            rng.fill_bytes(hmac_key.as_mut_slice());
+            let hmac_key = Zeroizing::new(hex::encode_upper(hmac_key.as_slice()));
+            let Some(path) = keystore else {
+                eprintln!("Signing key: {}", *key);
+                eprintln!("HMAC key: {}", *hmac_key);
+                return Ok(());
+            };
+            let secrets = keystore::Secrets {
+                signing_keys: vec![key.to_string()],
+                hmac_secret: hmac_key.to_string(),
+            };
+            keystore::write_new(&path, &keystore::encrypt(&secrets, &password.password()?)?)?;
+            eprintln!("Wrote keystore to {}", path.display());
+            eprintln!(
+                "Public key: {}",
+                hex::encode(signing_key.verifying_key().to_sec1_bytes())
+            );
+            Ok(())
+        }
+        Commands::AddKey {
+            keystore,
+            password,
+            valid_from,
+        } => {
+            let password = password.password()?;
+            let mut secrets = keystore::decrypt(
+                &SecretSource::Path(keystore.clone()).read("keystore")?,
+                &password,
+            )?;
+            for key in &mut secrets.signing_keys {
+                let current = key.parse::<RngKey>()?;
+                if current.valid_until.is_none() {
+                    if let Some(from) = current.valid_from {
+                        anyhow::ensure!(
+                            from < valid_from,
+                            "The newest key is valid from round {from}, pick a later --valid-from"
+                        );
+                    }
+                    // Keep the start of the window, if there is one.
+                    let (hex, window) = key.split_once('@').unwrap_or((key, "-"));
+                    let from = window.split_once('-').map_or("", |(from, _)| from);
+                    *key = format!("{hex}@{from}-{valid_from}");
+                }
+            }
+            let signing_key = SigningKey::random(&mut OsRng);
+            secrets.signing_keys.push(format!(
+                "{}@{valid_from}-",
+                *Zeroizing::new(hex::encode_upper(signing_key.to_bytes()))
+            ));
+            // Same checks as the server makes when loading them.
+            KeyRing::new(
+                secrets
+                    .signing_keys
+                    .iter()
+                    .map(|key| key.parse())
+                    .collect::<anyhow::Result<_>>()?,
+            )?;
+            // Write the new keystore next to the old one and swap it in, so
+            // a failure part way through never loses the existing keys.
+            let mut new = keystore.clone().into_os_string();
+            new.push(".new");
+            let new = PathBuf::from(new);
+            keystore::write_new(&new, &keystore::encrypt(&secrets, &password)?)?;
+            std::fs::rename(&new, &keystore)
+                .with_context(|| format!("Unable to replace {}", keystore.display()))?;
+            eprintln!("Updated keystore {}", keystore.display());
+            eprintln!(
+                "Public key valid from round {valid_from}: {}",
+                hex::encode(signing_key.verifying_key().to_sec1_bytes())
+            );
             Ok(())
         }
+        #[cfg(unix)]
+        Commands::Signer {
+            socket,
+            keystore,
+            password,
+            public_key,
+        } => {
+            let secrets = keystore::decrypt(&keystore.read("keystore")?, &password.password()?)?;
+            let mut keys = secrets
+                .signing_keys
+                .iter()
+                .map(|key| key.parse::<RngKey>())
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            if let Some(public_key) = public_key {
+                keys.retain(|key| *key.signer.verifying_key() == public_key);
+            }
+            let [key] = &keys[..] else {
+                anyhow::bail!(
+                    "Keystore has {} matching signing keys, pick one with --public-key",
+                    keys.len()
+                );
+            };
+            let signing_key = key
+                .signer
+                .signing_key()
+                .ok_or_else(|| anyhow::anyhow!("Impossible: keystore keys are held in process"))?;
+            tokio::task::block_in_place(|| signer::socket::serve(&socket, signing_key))
+        }
+        Commands::Verify {
+            public_keys,
+            hmac_secret,
+            input,
+        } => verify::run(&public_keys, input, hmac_secret.map(Zeroizing::new)),
     }
 }
 
-#[derive(Serialize)]
-struct Payload {
-    number: u32,
-    timestamp: u64,
+fn parse_public_key(s: &str) -> anyhow::Result<VerifyingKey> {
+    Ok(VerifyingKey::from_sec1_bytes(&hex::decode(s)?)?)
 }
 
-#[derive(Serialize)]
-struct Response {
-    number: u32,
-    signature: String,
-    recovery_id: u8,
-    serialized: String,
+/// Check a stream name taken from a request path.
+fn check_stream_name(name: Option<String>) -> Result<Option<String>, axum::http::StatusCode> {
+    match name {
+        Some(name) if !rng_protocol::is_valid_stream_name(&name) => {
+            Err(axum::http::StatusCode::BAD_REQUEST)
+        }
+        name => Ok(name),
+    }
+}
+
+/// Parse a round period such as `90`, `15s`, `5m` or `1h` into seconds.
+fn parse_round_period(s: &str) -> anyhow::Result<u64> {
+    let secs = rng_protocol::parse_duration(s)?;
+    anyhow::ensure!(secs > 0, "Round period must not be zero");
+    Ok(secs)
+}
+
+/// Path of a per-round endpoint, optionally under `/stream/{name}`.
+#[derive(Deserialize)]
+struct RoundPath {
+    name: Option<String>,
     timestamp: u64,
 }
 
+/// Optional range for [generate_signed_number], inclusive on both ends.
+#[derive(Deserialize)]
+struct BoundsQuery {
+    min: Option<u32>,
+    max: Option<u32>,
+}
+
 async fn generate_signed_number(
     State(state): State<AppState>,
-    Path(timestamp): Path<u64>,
-) -> Result<Json<Response>, axum::http::StatusCode> {
+    Path(RoundPath { name, timestamp }): Path<RoundPath>,
+    Query(BoundsQuery { min, max }): Query<BoundsQuery>,
+) -> Result<Json<Signed<RngResult>>, ApiError> {
+    let stream = check_stream_name(name)?;
+    let bounds = match (min, max) {
+        (None, None) => None,
+        (Some(min), Some(max)) if min <= max => Some((min, max)),
+        _ => return Err(axum::http::StatusCode::BAD_REQUEST.into()),
+    };
     // Prevent precognition: only allow past or current timestamps
-    let now_min = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
-        .as_secs()
-        / 60;
-    if timestamp > now_min {
+    let now = state
+        .current_round()
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    state
+        .metrics
+        .observe_round("/number/{timestamp}", timestamp, now);
+    state.check_started(timestamp)?;
+
+    Ok(Json(state.issue_round(stream, timestamp, bounds).await?))
+}
+
+#[derive(Deserialize)]
+struct RangeQuery {
+    /// First round, inclusive.
+    from: u64,
+    /// Last round, inclusive.
+    to: u64,
+}
+
+/// Signed results for a range of rounds, for clients catching up after downtime.
+async fn generate_signed_numbers(
+    State(state): State<AppState>,
+    name: Option<Path<String>>,
+    Query(RangeQuery { from, to }): Query<RangeQuery>,
+) -> Result<Json<Vec<Signed<RngResult>>>, ApiError> {
+    let stream = check_stream_name(name.map(|Path(name)| name))?;
+    if from > to || to - from >= MAX_RANGE {
+        return Err(axum::http::StatusCode::BAD_REQUEST.into());
+    }
+    // Same precognition check as for single rounds
+    let now = state
+        .current_round()
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    state.metrics.observe_round("/numbers", to, now);
+    state.check_started(to)?;
+
+    // Up to MAX_RANGE signatures, keep them off the async workers.
+    let results = tokio::task::spawn_blocking(move || {
+        (from..=to)
+            .map(|timestamp| state.signed_round(stream.as_deref(), timestamp, None))
+            .collect::<Result<_, _>>()
+    })
+    .await
+    .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)??;
+    Ok(Json(results))
+}
+
+/// Publish each round's signed result to [stream_numbers] subscribers as the round starts.
+async fn publish_rounds(state: AppState) {
+    loop {
+        let next = match state.current_round() {
+            Ok(now) => now + 1,
+            Err(e) => {
+                eprintln!("Unable to determine current round: {e}");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let start = UNIX_EPOCH + Duration::from_secs(next * state.round_period);
+        tokio::time::sleep(start.duration_since(SystemTime::now()).unwrap_or_default()).await;
+        // Don't publish early if the wall clock moved while we slept.
+        if state.current_round().is_ok_and(|now| now < next) {
+            continue;
+        }
+        match state.issue_round(None, next, None).await {
+            // Sending only fails when nobody is subscribed.
+            Ok(response) => _ = state.rounds.send(Arc::new(response)),
+            Err(status) => eprintln!("Unable to publish round {next}: {status}"),
+        }
+    }
+}
+
+/// Stop the server on SIGTERM or SIGINT.
+///
+/// New connections are refused straight away. Open requests get up to
+/// `timeout` to finish, `/stream` subscriptions are ended immediately.
+async fn shutdown_on_signal(state: AppState, handle: axum_server::Handle, timeout: Duration) {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                eprintln!("Unable to listen for SIGTERM: {e}");
+                std::future::pending().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => {
+            if let Err(e) = result {
+                eprintln!("Unable to listen for SIGINT: {e}");
+                return;
+            }
+        }
+        () = terminate => {}
+    }
+    println!(
+        "Shutting down, waiting up to {}s for open requests",
+        timeout.as_secs()
+    );
+    state.shutdown.send_replace(true);
+    handle.graceful_shutdown(Some(timeout));
+}
+
+/// Sign each day's audit root as soon as the day is over.
+async fn sign_audit_roots(state: AppState) {
+    let Some(audit) = state.audit.clone() else {
+        return;
+    };
+    loop {
+        let today = match audit::current_day() {
+            Ok(today) => today,
+            Err(e) => {
+                eprintln!("Unable to determine current day: {e}");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            }
+        };
+        // Also covers yesterday if the server was down when it ended.
+        if let Some(yesterday) = today.checked_sub(1) {
+            let (state, audit) = (state.clone(), audit.clone());
+            let signed =
+                tokio::task::spawn_blocking(move || state.sign_audit_root(&audit, yesterday))
+                    .await
+                    .unwrap_or_else(|e| Err(e.into()));
+            if let Err(e) = signed {
+                eprintln!("Unable to sign audit root for day {yesterday}: {e}");
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                continue;
+            }
+        }
+        let tomorrow = audit::day_start(today + 1);
+        tokio::time::sleep(
+            tomorrow
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+        )
+        .await;
+    }
+}
+
+#[derive(Serialize)]
+struct AuditResponse {
+    day: u64,
+    leaves: Vec<audit::Leaf>,
+    /// Hex-encoded Merkle root of the leaves so far.
+    root: String,
+    /// Only present once the day is over.
+    signed_root: Option<audit::SignedRoot>,
+}
+
+/// Everything issued on the given UTC day, counted from the epoch.
+async fn audit_day(
+    State(state): State<AppState>,
+    Path(day): Path<u64>,
+) -> Result<Json<AuditResponse>, axum::http::StatusCode> {
+    let audit = state
+        .audit
+        .clone()
+        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
+    let internal_error = |e: anyhow::Error| {
+        eprintln!("Unable to serve audit log for day {day}: {e}");
+        axum::http::StatusCode::INTERNAL_SERVER_ERROR
+    };
+    let today = audit::current_day().map_err(internal_error)?;
+    if day > today {
         return Err(axum::http::StatusCode::NOT_FOUND);
     }
+    let response = tokio::task::spawn_blocking(move || {
+        let leaves = audit.leaves(day)?;
+        let root = hex::encode(audit::merkle_root(&leaves)?);
+        let signed_root = if day < today {
+            Some(state.sign_audit_root(&audit, day)?)
+        } else {
+            None
+        };
+        anyhow::Ok(AuditResponse {
+            day,
+            leaves,
+            root,
+            signed_root,
+        })
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.into()))
+    .map_err(internal_error)?;
+    Ok(Json(response))
+}
 
-    let mut mac = state.hmac_template.clone();
-    mac.update(&timestamp.to_be_bytes());
-    let result = mac.finalize().into_bytes();
-    // Take the first 4 bytes as a number
-    let number: u32 = result[0..4]
-        .try_into()
-        .map(u32::from_be_bytes)
+/// Server-Sent Events stream of signed results, one `round` event per round.
+///
+/// Starts with the current round, so a subscriber that connects
+/// mid-round doesn't have to wait for the next one.
+async fn stream_numbers(
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, axum::http::StatusCode> {
+    let receiver = state.rounds.subscribe();
+    let now = state
+        .current_round()
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
-    let payload = Payload { number, timestamp };
+    let current = Arc::new(state.issue_round(None, now, None).await?);
+    let later = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(response) => return Some((response, receiver)),
+                // Slow subscribers skip rounds rather than closing the stream,
+                // they can fetch what they missed from /numbers.
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+    let mut shutdown = state.shutdown.subscribe();
+    let later = later.take_until(async move {
+        // An error means the sender is gone, in which case so is the server.
+        let _ = shutdown.wait_for(|&shutdown| shutdown).await;
+    });
+    let events = stream::iter([current]).chain(later).map(|response| {
+        Event::default()
+            .event("round")
+            .id(response.fields.timestamp.to_string())
+            .json_data(&*response)
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
 
-    let serialized = serde_json::to_string(&payload)
+/// This server's share of a round, for games combining several independent operators.
+///
+/// The share is a threshold VRF proof under our share of the group key, see
+/// [rng_protocol::threshold]. Games combine any `threshold` of them into
+/// the same seed, so neither a single operator nor whoever picks the
+/// shares controls the outcome.
+async fn generate_share(
+    State(state): State<AppState>,
+    Path(RoundPath { name, timestamp }): Path<RoundPath>,
+) -> Result<Json<Signed<RngShare>>, ApiError> {
+    let stream = check_stream_name(name)?;
+    // Same precognition check as for the number itself
+    let now = state
+        .current_round()
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    state
+        .metrics
+        .observe_round("/share/{timestamp}", timestamp, now);
+    state.check_started(timestamp)?;
 
-    let (signature, recovery_id) = state
-        .signing_key
-        .sign_recoverable(serialized.as_bytes())
+    let group_key = state
+        .share_group_key
+        .as_ref()
+        .ok_or(axum::http::StatusCode::NOT_FOUND)?;
+    let alpha = rng_protocol::seed_input(stream.as_deref(), timestamp)
         .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let key = state
+        .key_for(timestamp)?
+        .signing_key()
+        .ok_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let proof = rng_protocol::threshold::prove_share(key, group_key, &alpha)
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(state.sign(
+        timestamp,
+        RngShare {
+            share: hex::encode(proof),
+            timestamp,
+            stream,
+        },
+    )?))
+}
 
-    Ok(Json(Response {
-        number,
-        signature: signature.to_string(),
-        recovery_id: recovery_id.to_byte(),
-        serialized,
+/// Commit to a round's output ahead of time.
+///
+/// Unlike [generate_signed_number], this is available for future rounds:
+/// the commitment is a hash of the seed and reveals nothing about the number.
+async fn generate_commitment(
+    State(state): State<AppState>,
+    Path(RoundPath { name, timestamp }): Path<RoundPath>,
+) -> Result<Json<Signed<RngCommitment>>, ApiError> {
+    let stream = check_stream_name(name)?;
+    let now = state
+        .current_round()
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    state
+        .metrics
+        .observe_round("/commitment/{timestamp}", timestamp, now);
+    let (seed, _proof) = state.seed(stream.as_deref(), timestamp)?;
+    Ok(Json(state.sign(
         timestamp,
-    }))
+        RngCommitment {
+            commitment: hex::encode(Sha256::digest(seed)),
+            timestamp,
+            stream,
+        },
+    )?))
 }
 
-async fn public_key(State(state): State<AppState>) -> Json<String> {
-    let public_key = state.signing_key.verifying_key();
-    Json(hex::encode(public_key.to_sec1_bytes()))
+impl AppState {
+    /// The round that has most recently started.
+    fn current_round(&self) -> anyhow::Result<u64> {
+        Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() / self.round_period)
+    }
+
+    /// Check that a round has started, or will within `clock_skew`.
+    ///
+    /// Otherwise fails with `round_not_ready`, telling the client how long
+    /// to wait before asking again.
+    fn check_started(&self, timestamp: u64) -> Result<(), ApiError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
+            + Duration::from_secs(self.clock_skew);
+        let start = Duration::from_secs(timestamp.saturating_mul(self.round_period));
+        if now >= start {
+            return Ok(());
+        }
+        let wait = start - now;
+        // Round up, so retrying right after the wait always succeeds
+        let retry_after = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+        Err(ApiError::round_not_ready(timestamp, retry_after))
+    }
+
+    /// Derive the seed for the given round of a stream, along with a VRF proof if enabled.
+    fn seed(
+        &self,
+        stream: Option<&str>,
+        timestamp: u64,
+    ) -> Result<([u8; 32], Option<[u8; vrf::PROOF_LEN]>), axum::http::StatusCode> {
+        match &self.seed_source {
+            SeedSource::Hmac(hmac_key) => Ok((
+                rng_protocol::hmac_seed(hmac_key, stream, timestamp)
+                    .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?,
+                None,
+            )),
+            SeedSource::Vrf => {
+                let alpha = rng_protocol::seed_input(stream, timestamp)
+                    .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+                let key = self
+                    .key_for(timestamp)?
+                    .signing_key()
+                    .ok_or(axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+                let (proof, output) = vrf::prove(key, &alpha)
+                    .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+                Ok((output, Some(proof)))
+            }
+        }
+    }
+
+    /// Check every signing key works, and that the expected one is among them.
+    fn self_test(&self, expected: Option<&VerifyingKey>) -> anyhow::Result<()> {
+        for key in self.keys.iter() {
+            let fingerprint = metrics::fingerprint(key.signer.verifying_key());
+            signer::self_test(&*key.signer)
+                .with_context(|| format!("Self-test failed for signing key {fingerprint}"))?;
+        }
+        if let Some(expected) = expected {
+            anyhow::ensure!(
+                self.keys
+                    .iter()
+                    .any(|key| key.signer.verifying_key() == expected),
+                "Public key mismatch: expected {}, but signing with {}",
+                hex::encode(expected.to_sec1_bytes()),
+                self.keys
+                    .iter()
+                    .map(|key| hex::encode(key.signer.verifying_key().to_sec1_bytes()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+        println!("Self-test passed for all signing keys");
+        Ok(())
+    }
+
+    /// The signer valid for the given round.
+    fn key_for(&self, timestamp: u64) -> Result<&dyn Signer, axum::http::StatusCode> {
+        self.keys
+            .for_round(timestamp)
+            .ok_or(axum::http::StatusCode::NOT_FOUND)
+    }
+
+    /// Serialize and sign the given payload with the key for the given round.
+    ///
+    /// Returns the serialized JSON, the signature and the recovery ID.
+    fn sign_json(
+        &self,
+        timestamp: u64,
+        payload: &impl Serialize,
+    ) -> Result<(String, String, u8), axum::http::StatusCode> {
+        let serialized = rng_protocol::canonical(payload)
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        let started = Instant::now();
+        let signed = self.key_for(timestamp).and_then(|key| {
+            key.sign(serialized.as_bytes()).map_err(|e| {
+                eprintln!("Signing failed: {e:#}");
+                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            })
+        });
+        self.metrics.observe_signing(started, signed.is_ok());
+        let (signature, recovery_id) = signed?;
+
+        Ok((serialized, signature.to_string(), recovery_id.to_byte()))
+    }
+
+    /// Sign the given payload with the key for the given round.
+    fn sign<T: Serialize>(
+        &self,
+        timestamp: u64,
+        payload: T,
+    ) -> Result<Signed<T>, axum::http::StatusCode> {
+        let (serialized, signature, recovery_id) = self.sign_json(timestamp, &payload)?;
+        Ok(Signed {
+            fields: payload,
+            signature,
+            recovery_id,
+            serialized,
+        })
+    }
+
+    /// The serialized bundle for a finished day, generating it if it isn't cached.
+    async fn cached_bundle(&self, day: u64) -> Result<axum::body::Bytes, axum::http::StatusCode> {
+        if let Some(body) = self.bundles.get(day)? {
+            return Ok(body);
+        }
+        let _generating = self.bundles.generating.lock().await;
+        // Someone else may have generated it while we waited our turn.
+        if let Some(body) = self.bundles.get(day)? {
+            return Ok(body);
+        }
+        let state = self.clone();
+        let body = tokio::task::spawn_blocking(move || {
+            serde_json::to_vec(&state.bundle(day)?)
+                .map(axum::body::Bytes::from)
+                .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)
+        })
+        .await
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)??;
+        self.bundles.insert(day, body.clone())?;
+        Ok(body)
+    }
+
+    /// Every result of the given day, see [RngBundle].
+    ///
+    /// The root is signed by the key for the day's last round. The results
+    /// aren't written to the audit log: they can be regenerated at any
+    /// time, and are all covered by the signed bundle root anyway.
+    fn bundle(&self, day: u64) -> Result<RngBundle, axum::http::StatusCode> {
+        let rounds = rng_protocol::day_rounds(day, self.round_period);
+        let results = rounds
+            .clone()
+            .map(|timestamp| self.sign_round(None, timestamp, None))
+            .collect::<Result<Vec<_>, _>>()?;
+        let leaves = results
+            .iter()
+            .map(rng_protocol::bundle_leaf)
+            .collect::<anyhow::Result<_>>()
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        let root = BundleRoot {
+            date: rng_protocol::format_date(day),
+            round_period: self.round_period,
+            first: *rounds.start(),
+            last: *rounds.end(),
+            root: hex::encode(rng_protocol::merkle_root(leaves)),
+        };
+        Ok(RngBundle {
+            root: self.sign(*rounds.end(), root)?,
+            results,
+        })
+    }
+
+    fn payload(
+        &self,
+        stream: Option<&str>,
+        timestamp: u64,
+        previous_signature_hash: Option<String>,
+        bounds: Option<(u32, u32)>,
+    ) -> Result<RngResult, axum::http::StatusCode> {
+        let (seed, proof) = self.seed(stream, timestamp)?;
+        Ok(RngResult {
+            number: rng_protocol::derive_number(&seed, bounds),
+            timestamp,
+            stream: stream.map(str::to_owned),
+            seed: Some(hex::encode(seed)),
+            proof: proof.map(hex::encode),
+            previous_signature_hash,
+            min: bounds.map(|(min, _)| min),
+            max: bounds.map(|(_, max)| max),
+        })
+    }
+
+    /// [AppState::signed_round] on the blocking pool, as it may write to the audit log.
+    async fn issue_round(
+        &self,
+        stream: Option<String>,
+        timestamp: u64,
+        bounds: Option<(u32, u32)>,
+    ) -> Result<Signed<RngResult>, axum::http::StatusCode> {
+        let state = self.clone();
+        tokio::task::spawn_blocking(move || {
+            state.signed_round(stream.as_deref(), timestamp, bounds)
+        })
+        .await
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
+    }
+
+    /// Produce the signed result for the given round, optionally drawn from a range,
+    /// and record it in the audit log.
+    ///
+    /// Does not check whether the round is in the future, that's up to the caller.
+    fn signed_round(
+        &self,
+        stream: Option<&str>,
+        timestamp: u64,
+        bounds: Option<(u32, u32)>,
+    ) -> Result<Signed<RngResult>, axum::http::StatusCode> {
+        let response = self.sign_round(stream, timestamp, bounds)?;
+        if let Some(audit) = &self.audit {
+            // Never hand out a result we couldn't log.
+            audit
+                .record(
+                    timestamp,
+                    &response.serialized,
+                    &response.signature,
+                    response.recovery_id,
+                )
+                .map_err(|e| {
+                    eprintln!("Unable to log round {timestamp}: {e}");
+                    axum::http::StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+        }
+        Ok(response)
+    }
+
+    /// Like [AppState::signed_round], without recording the result in the audit log.
+    fn sign_round(
+        &self,
+        stream: Option<&str>,
+        timestamp: u64,
+        bounds: Option<(u32, u32)>,
+    ) -> Result<Signed<RngResult>, axum::http::StatusCode> {
+        let previous_signature_hash = match (&self.beacon, stream) {
+            (None, _) => None,
+            // Only the default stream is chained. Chaining named streams too
+            // would let anyone make us sign a whole chain per new name.
+            (Some(_), Some(_)) => return Err(axum::http::StatusCode::NOT_FOUND),
+            (Some(beacon), None) => beacon.previous_link(self, timestamp)?.map(hex::encode),
+        };
+        self.sign(
+            timestamp,
+            self.payload(stream, timestamp, previous_signature_hash, bounds)?,
+        )
+    }
+
+    /// Sign the Merkle root of a completed day's log, unless it's already signed.
+    fn sign_audit_root(&self, audit: &AuditLog, day: u64) -> anyhow::Result<audit::SignedRoot> {
+        if let Some(signed_root) = audit.signed_root(day)? {
+            return Ok(signed_root);
+        }
+        let leaves = audit.leaves(day)?;
+        let payload = audit::RootPayload {
+            day,
+            leaves: leaves.len() as u64,
+            root: hex::encode(audit::merkle_root(&leaves)?),
+        };
+        let (serialized, signature, recovery_id) = self
+            .sign_json(self.current_round()?, &payload)
+            .map_err(|status| anyhow::anyhow!("Unable to sign audit root: {status}"))?;
+        let audit::RootPayload { day, leaves, root } = payload;
+        audit.store_root(audit::SignedRoot {
+            day,
+            leaves,
+            root,
+            signature,
+            recovery_id,
+            serialized,
+        })
+    }
+}
+
+impl Beacon {
+    /// Get the signature hash of the round before the given one.
+    ///
+    /// Returns [None] for the genesis round, which has no predecessor.
+    fn previous_link(
+        &self,
+        state: &AppState,
+        timestamp: u64,
+    ) -> Result<Option<[u8; 32]>, axum::http::StatusCode> {
+        let Some(rounds) = timestamp.checked_sub(self.genesis) else {
+            // Rounds before genesis are not part of the beacon.
+            return Err(axum::http::StatusCode::NOT_FOUND);
+        };
+        let rounds =
+            usize::try_from(rounds).map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        if rounds == 0 {
+            return Ok(None);
+        }
+        let mut links = self
+            .links
+            .lock()
+            .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+        while links.len() < rounds {
+            let round = self.genesis + links.len() as u64;
+            let payload = state.payload(None, round, links.last().map(hex::encode), None)?;
+            let response = state.sign(round, payload)?;
+            links.push(
+                rng_protocol::signature_hash(&response.signature)
+                    .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?,
+            );
+        }
+        Ok(Some(links[rounds - 1]))
+    }
+}
+
+/// Every result of a UTC day, with a signed Merkle root over them.
+///
+/// Available once the day's last round has started. Building a bundle
+/// signs every round of the day, so it's done off the async workers and
+/// cached afterwards, see [BundleCache].
+async fn bundle_day(
+    State(state): State<AppState>,
+    Path(date): Path<String>,
+) -> Result<axum::response::Response, ApiError> {
+    let day = rng_protocol::parse_date(&date).map_err(|_| axum::http::StatusCode::BAD_REQUEST)?;
+    state.check_started(*rng_protocol::day_rounds(day, state.round_period).end())?;
+    let body = state.cached_bundle(day).await?;
+    Ok((
+        [(axum::http::header::CONTENT_TYPE, "application/json")],
+        body,
+    )
+        .into_response())
+}
+
+/// The public key for the current round.
+async fn public_key(State(state): State<AppState>) -> Result<Json<String>, axum::http::StatusCode> {
+    let now = state
+        .current_round()
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let public_key = state.key_for(now)?.verifying_key();
+    Ok(Json(hex::encode(public_key.to_sec1_bytes())))
+}
+
+/// All public keys with their validity windows, signed by the current key.
+async fn public_keys(
+    State(state): State<AppState>,
+) -> Result<Json<Signed<RngKeySet>>, axum::http::StatusCode> {
+    let issued = state
+        .current_round()
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?;
+    let payload = RngKeySet {
+        keys: state
+            .keys
+            .iter()
+            .map(|key| RngKeyInfo {
+                public_key: hex::encode(key.signer.verifying_key().to_sec1_bytes()),
+                valid_from: key.valid_from,
+                valid_until: key.valid_until,
+            })
+            .collect(),
+        issued,
+    };
+    state.sign(issued, payload).map(Json)
+}
+
+/// Length of each round in seconds.
+async fn round_period_secs(State(state): State<AppState>) -> Json<u64> {
+    Json(state.round_period)
 }
 
 async fn health() -> &'static str {
     "Healthy!"
 }
+
+/// Ready once the key for the current round passes the signing self-test.
+///
+/// Unlike `/healthz`, this fails while an external signer is down.
+async fn ready(State(state): State<AppState>) -> Result<Json<RngReadiness>, ApiError> {
+    let server_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| axum::http::StatusCode::INTERNAL_SERVER_ERROR)?
+        .as_secs();
+    let current_round = server_time / state.round_period;
+    let signer = state
+        .keys
+        .for_round(current_round)
+        .ok_or_else(|| ApiError::not_ready(format!("No signing key for round {current_round}")))?;
+    let fingerprint = metrics::fingerprint(signer.verifying_key());
+    signer::self_test(signer).map_err(|e| {
+        eprintln!("Self-test failed for signing key {fingerprint}: {e:#}");
+        ApiError::not_ready(format!("Self-test failed for signing key {fingerprint}"))
+    })?;
+    Ok(Json(RngReadiness {
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        public_key: hex::encode(signer.verifying_key().to_sec1_bytes()),
+        fingerprint,
+        server_time,
+        current_round,
+        round_period: state.round_period,
+    }))
+}
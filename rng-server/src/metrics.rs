@@ -0,0 +1,164 @@
+//! Prometheus metrics, served from `/metrics`.
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use k256::ecdsa::VerifyingKey;
+use k256::sha2::{Digest, Sha256};
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+
+use crate::AppState;
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct RequestLabels {
+    endpoint: String,
+    status: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct EndpointLabels {
+    endpoint: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct KeyLabels {
+    fingerprint: String,
+}
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    requests: Family<RequestLabels, Counter>,
+    signing_duration: Histogram,
+    signing_failures: Counter,
+    round_offset: Family<EndpointLabels, Histogram, fn() -> Histogram>,
+    active_key: Family<KeyLabels, Gauge>,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Self {
+        let mut registry = Registry::with_prefix("rng");
+        let requests = Family::<RequestLabels, Counter>::default();
+        registry.register(
+            "requests",
+            "HTTP requests by endpoint and status code",
+            requests.clone(),
+        );
+        let signing_duration = Histogram::new(exponential_buckets(0.0001, 2.0, 12));
+        registry.register(
+            "signing_duration_seconds",
+            "Time taken to sign a payload",
+            signing_duration.clone(),
+        );
+        let signing_failures = Counter::default();
+        registry.register(
+            "signing_failures",
+            "Payloads that could not be signed",
+            signing_failures.clone(),
+        );
+        let round_offset =
+            Family::<EndpointLabels, Histogram, fn() -> Histogram>::new_with_constructor(|| {
+                Histogram::new([-1440.0, -60.0, -10.0, -1.0, 0.0, 1.0, 10.0, 60.0, 1440.0])
+            });
+        registry.register(
+            "requested_round_offset",
+            "Requested round minus the current round, positive for future rounds",
+            round_offset.clone(),
+        );
+        let active_key = Family::<KeyLabels, Gauge>::default();
+        registry.register(
+            "active_key",
+            "1 for the fingerprint of the key signing the current round, 0 for other keys",
+            active_key.clone(),
+        );
+        Metrics {
+            registry,
+            requests,
+            signing_duration,
+            signing_failures,
+            round_offset,
+            active_key,
+        }
+    }
+
+    /// Record how far a requested round is from the current one.
+    pub(crate) fn observe_round(&self, endpoint: &str, timestamp: u64, now: u64) {
+        self.round_offset
+            .get_or_create(&EndpointLabels {
+                endpoint: endpoint.to_owned(),
+            })
+            .observe(timestamp as f64 - now as f64);
+    }
+
+    /// Record a signing attempt that started at the given time.
+    pub(crate) fn observe_signing(&self, started: Instant, success: bool) {
+        self.signing_duration
+            .observe(started.elapsed().as_secs_f64());
+        if !success {
+            self.signing_failures.inc();
+        }
+    }
+}
+
+/// Count every request by its route and response status.
+pub(crate) async fn track_requests(
+    State(state): State<AppState>,
+    path: Option<MatchedPath>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let endpoint = path.map_or_else(|| "unknown".to_owned(), |path| path.as_str().to_owned());
+    state
+        .metrics
+        .requests
+        .get_or_create(&RequestLabels {
+            endpoint,
+            status: response.status().as_u16().to_string(),
+        })
+        .inc();
+    response
+}
+
+pub(crate) async fn metrics(State(state): State<AppState>) -> Response {
+    let active = state
+        .current_round()
+        .ok()
+        .and_then(|now| state.keys.for_round(now))
+        .map(|signer| fingerprint(signer.verifying_key()));
+    for key in state.keys.iter() {
+        let fingerprint = fingerprint(key.signer.verifying_key());
+        let is_active = active.as_ref() == Some(&fingerprint);
+        state
+            .metrics
+            .active_key
+            .get_or_create(&KeyLabels { fingerprint })
+            .set(i64::from(is_active));
+    }
+
+    let mut body = String::new();
+    if let Err(e) = prometheus_client::encoding::text::encode(&mut body, &state.metrics.registry) {
+        eprintln!("Unable to encode metrics: {e}");
+        return axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    }
+    (
+        [(
+            axum::http::header::CONTENT_TYPE,
+            "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        )],
+        body,
+    )
+        .into_response()
+}
+
+/// Short, stable identifier for a key: the first 8 bytes of the SHA-256
+/// of its compressed public key, hex encoded.
+pub(crate) fn fingerprint(key: &VerifyingKey) -> String {
+    let digest = Sha256::digest(key.to_sec1_bytes());
+    hex::encode(&digest[..8])
+}
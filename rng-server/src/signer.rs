@@ -0,0 +1,121 @@
+//! Backends producing the server's signatures.
+//!
+//! The HTTP layer only sees [Signer]. The key is either held in process,
+//! or stays outside it: behind an external signer process, see [socket],
+//! or in a PKCS#11 module such as an HSM.
+#[cfg(feature = "pkcs11")]
+mod pkcs11;
+#[cfg(unix)]
+pub(crate) mod socket;
+
+use std::sync::Arc;
+
+use k256::ecdsa::signature::Verifier;
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use k256::sha2::{Digest, Sha256};
+
+/// Signed by [self_test]. Not valid JSON, so it can't pass for any response.
+const SELF_TEST_MESSAGE: &[u8] = b"rng-server self-test";
+
+/// Something that signs with a secp256k1 key.
+pub(crate) trait Signer: Send + Sync {
+    /// Public key the signatures verify under.
+    fn verifying_key(&self) -> &VerifyingKey;
+
+    /// Sign the SHA-256 of a message, returning a low-S signature and its recovery ID.
+    fn sign(&self, message: &[u8]) -> anyhow::Result<(Signature, RecoveryId)>;
+
+    /// The key itself, if it's held in process.
+    ///
+    /// VRF mode needs it for its proofs, and beacon mode for signatures
+    /// it can recompute after a restart.
+    fn signing_key(&self) -> Option<&SigningKey> {
+        None
+    }
+}
+
+/// An in-process key.
+impl Signer for SigningKey {
+    fn verifying_key(&self) -> &VerifyingKey {
+        SigningKey::verifying_key(self)
+    }
+
+    fn sign(&self, message: &[u8]) -> anyhow::Result<(Signature, RecoveryId)> {
+        Ok(self.sign_recoverable(message)?)
+    }
+
+    fn signing_key(&self) -> Option<&SigningKey> {
+        Some(self)
+    }
+}
+
+/// Where to sign when the key isn't passed in directly.
+///
+/// The key then never enters this process. It can't be rotated, and
+/// neither VRF nor beacon mode are available.
+#[derive(clap::Args)]
+pub(crate) struct SignerOpts {
+    /// Sign with an external signer process listening on this Unix socket,
+    /// such as `rng-server signer`.
+    #[cfg(unix)]
+    #[clap(
+        long,
+        env = "RARE_EVO_SIGNER_SOCKET",
+        conflicts_with_all = ["signing_keys", "keystore"]
+    )]
+    signer_socket: Option<std::path::PathBuf>,
+    #[cfg(feature = "pkcs11")]
+    #[clap(flatten)]
+    pkcs11: pkcs11::Pkcs11Opts,
+}
+
+impl SignerOpts {
+    /// Load the configured external signer, [None] if keys are passed in directly.
+    pub(crate) fn load(self) -> anyhow::Result<Option<Arc<dyn Signer>>> {
+        #[cfg(unix)]
+        if let Some(path) = self.signer_socket {
+            println!("Signing with the external signer at {}", path.display());
+            return Ok(Some(Arc::new(socket::SocketSigner::connect(path)?)));
+        }
+        #[cfg(feature = "pkcs11")]
+        if let Some(signer) = self.pkcs11.load()? {
+            return Ok(Some(Arc::new(signer)));
+        }
+        Ok(None)
+    }
+}
+
+/// Sign a test message and check the signature verifies, and recovers to the signer's key.
+///
+/// Catches a broken or misconfigured backend before it's asked to sign
+/// anything that gets published.
+pub(crate) fn self_test(signer: &dyn Signer) -> anyhow::Result<()> {
+    let (signature, recovery_id) = signer.sign(SELF_TEST_MESSAGE)?;
+    signer
+        .verifying_key()
+        .verify(SELF_TEST_MESSAGE, &signature)
+        .map_err(|_| anyhow::anyhow!("Self-test signature does not verify"))?;
+    let recovered = VerifyingKey::recover_from_msg(SELF_TEST_MESSAGE, &signature, recovery_id)?;
+    anyhow::ensure!(
+        &recovered == signer.verifying_key(),
+        "Self-test signature recovers to the wrong public key"
+    );
+    Ok(())
+}
+
+/// Normalize a signature from outside the process and find its recovery ID.
+///
+/// This also checks the signature against the key we expect, so a
+/// misbehaving signer can't get anything else published.
+#[cfg(any(unix, feature = "pkcs11"))]
+fn recoverable(
+    verifying_key: &VerifyingKey,
+    message: &[u8],
+    signature: Signature,
+) -> anyhow::Result<(Signature, RecoveryId)> {
+    let signature = signature.normalize_s().unwrap_or(signature);
+    let prehash = Sha256::digest(message);
+    let recovery_id = RecoveryId::trial_recovery_from_prehash(verifying_key, &prehash, &signature)
+        .map_err(|_| anyhow::anyhow!("Signature does not match the signer's public key"))?;
+    Ok((signature, recovery_id))
+}
@@ -0,0 +1,180 @@
+//! Signing with a key held in a PKCS#11 module, such as an HSM.
+//!
+//! The key pair must be on the secp256k1 curve, with the same label on
+//! its private and public key objects. For local testing SoftHSM works:
+//!
+//! ```text
+//! softhsm2-util --init-token --free --label rng --pin 1234 --so-pin 1234
+//! pkcs11-tool --module /usr/lib/softhsm/libsofthsm2.so --token-label rng \
+//!     --login --pin 1234 --keypairgen --key-type EC:secp256k1 --label rng-server
+//! ```
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use cryptoki::context::{CInitializeArgs, Pkcs11};
+use cryptoki::mechanism::Mechanism;
+use cryptoki::object::{Attribute, AttributeType, ObjectClass, ObjectHandle};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use k256::sha2::{Digest, Sha256};
+use zeroize::Zeroizing;
+
+use super::{recoverable, Signer};
+
+#[derive(clap::Args)]
+pub(super) struct Pkcs11Opts {
+    /// Sign with a key in this PKCS#11 module, such as SoftHSM's
+    /// `libsofthsm2.so`.
+    #[clap(
+        long,
+        env = "RARE_EVO_PKCS11_MODULE",
+        conflicts_with_all = ["signing_keys", "keystore", "signer_socket"]
+    )]
+    pkcs11_module: Option<PathBuf>,
+    /// Label of the token holding the key, the first token found if not given.
+    #[clap(long, env = "RARE_EVO_PKCS11_TOKEN", requires = "pkcs11_module")]
+    pkcs11_token: Option<String>,
+    /// Label of the key pair on the token.
+    #[clap(long, env = "RARE_EVO_PKCS11_KEY", default_value = "rng-server")]
+    pkcs11_key: String,
+    /// User PIN for the token.
+    #[clap(
+        long,
+        env = "RARE_EVO_PKCS11_PIN",
+        hide_env_values = true,
+        requires = "pkcs11_module"
+    )]
+    pkcs11_pin: Option<String>,
+}
+
+impl Pkcs11Opts {
+    pub(super) fn load(self) -> anyhow::Result<Option<Pkcs11Signer>> {
+        let Some(module) = self.pkcs11_module else {
+            return Ok(None);
+        };
+        let pin = self.pkcs11_pin.map(Zeroizing::new);
+        let signer = Pkcs11Signer::open(
+            &module,
+            self.pkcs11_token.as_deref(),
+            &self.pkcs11_key,
+            pin.as_deref().map(String::as_str),
+        )
+        .with_context(|| format!("Unable to load key from {}", module.display()))?;
+        println!(
+            "Signing with PKCS#11 key {} from {}",
+            self.pkcs11_key,
+            module.display()
+        );
+        Ok(Some(signer))
+    }
+}
+
+/// A key pair in a PKCS#11 token.
+pub(super) struct Pkcs11Signer {
+    /// Sessions can't be shared between threads, so we take turns.
+    session: Mutex<Session>,
+    private_key: ObjectHandle,
+    verifying_key: VerifyingKey,
+    /// Keeps the module loaded for as long as the session is open.
+    _context: Pkcs11,
+}
+
+impl Pkcs11Signer {
+    fn open(
+        module: &std::path::Path,
+        token: Option<&str>,
+        label: &str,
+        pin: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let context = Pkcs11::new(module)?;
+        context.initialize(CInitializeArgs::OsThreads)?;
+        let mut slot = None;
+        for candidate in context.get_slots_with_token()? {
+            let info = context.get_token_info(candidate)?;
+            if token.is_none_or(|token| info.label() == token) {
+                slot = Some(candidate);
+                break;
+            }
+        }
+        let slot = slot.with_context(|| match token {
+            Some(token) => format!("No token labelled {token}"),
+            None => "No token found".to_owned(),
+        })?;
+        let session = context.open_ro_session(slot)?;
+        if let Some(pin) = pin {
+            session.login(UserType::User, Some(&AuthPin::new(pin.to_owned())))?;
+        }
+
+        let find = |class, name: &str| -> anyhow::Result<ObjectHandle> {
+            let template = [
+                Attribute::Class(class),
+                Attribute::Label(label.as_bytes().to_vec()),
+            ];
+            let mut objects = session.find_objects(&template)?;
+            anyhow::ensure!(
+                objects.len() == 1,
+                "Expected one {name} labelled {label}, found {}",
+                objects.len()
+            );
+            Ok(objects.remove(0))
+        };
+        let private_key = find(ObjectClass::PRIVATE_KEY, "private key")?;
+        let public_key = find(ObjectClass::PUBLIC_KEY, "public key")?;
+        let ec_point = session
+            .get_attributes(public_key, &[AttributeType::EcPoint])?
+            .into_iter()
+            .find_map(|attribute| match attribute {
+                Attribute::EcPoint(point) => Some(point),
+                _ => None,
+            })
+            .context("Public key has no EC point")?;
+        let verifying_key = VerifyingKey::from_sec1_bytes(unwrap_octet_string(&ec_point))
+            .context("Key is not on the secp256k1 curve")?;
+
+        Ok(Pkcs11Signer {
+            session: Mutex::new(session),
+            private_key,
+            verifying_key,
+            _context: context,
+        })
+    }
+}
+
+/// Modules should return the EC point DER-encoded as an OCTET STRING, but
+/// some return the raw point.
+fn unwrap_octet_string(point: &[u8]) -> &[u8] {
+    match point {
+        // A compressed or uncompressed point, as a short-form OCTET STRING
+        [0x04, len, rest @ ..]
+            if usize::from(*len) == rest.len() && matches!(rest.len(), 33 | 65) =>
+        {
+            rest
+        }
+        _ => point,
+    }
+}
+
+impl Signer for Pkcs11Signer {
+    fn verifying_key(&self) -> &VerifyingKey {
+        &self.verifying_key
+    }
+
+    fn sign(&self, message: &[u8]) -> anyhow::Result<(Signature, RecoveryId)> {
+        // CKM_ECDSA signs a hash computed by the caller.
+        let prehash = Sha256::digest(message);
+        let signature = tokio::task::block_in_place(|| {
+            self.session
+                .lock()
+                .map_err(|_| anyhow::anyhow!("PKCS#11 session lock poisoned"))?
+                .sign(&Mechanism::Ecdsa, self.private_key, &prehash)
+                .context("PKCS#11 signing failed")
+        })?;
+        recoverable(
+            &self.verifying_key,
+            message,
+            Signature::from_slice(&signature)?,
+        )
+    }
+}
@@ -0,0 +1,200 @@
+//! External signer processes, talking JSON over a Unix socket.
+//!
+//! Each connection carries a single request and its response, each on a
+//! line of its own:
+//!
+//! - `{"method":"public_key"}` is answered with `{"public_key":"HEX"}`,
+//!   the compressed SEC1 public key.
+//! - `{"method":"sign","message":"HEX"}` is answered with
+//!   `{"signature":"HEX"}`, the 64-byte ECDSA signature over the SHA-256
+//!   of the message.
+//!
+//! Either may be answered with `{"error":"..."}` instead. [serve] is such
+//! a process, run with `rng-server signer`.
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::Context;
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use super::{recoverable, Signer};
+
+/// How long to wait on the other end before giving up on a request.
+const SOCKET_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+enum Request {
+    PublicKey,
+    Sign { message: String },
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Response {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    public_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Response {
+    fn error(error: String) -> Self {
+        Response {
+            error: Some(error),
+            ..Response::default()
+        }
+    }
+}
+
+/// An external signer process listening on a Unix socket.
+pub(crate) struct SocketSigner {
+    path: PathBuf,
+    verifying_key: VerifyingKey,
+}
+
+impl SocketSigner {
+    /// Ask the signer at `path` for its public key.
+    pub(super) fn connect(path: PathBuf) -> anyhow::Result<Self> {
+        let public_key = call(&path, &Request::PublicKey)?
+            .public_key
+            .context("External signer did not return its public key")?;
+        let verifying_key = VerifyingKey::from_sec1_bytes(&hex::decode(public_key)?)?;
+        Ok(SocketSigner {
+            path,
+            verifying_key,
+        })
+    }
+}
+
+impl Signer for SocketSigner {
+    fn verifying_key(&self) -> &VerifyingKey {
+        &self.verifying_key
+    }
+
+    fn sign(&self, message: &[u8]) -> anyhow::Result<(Signature, RecoveryId)> {
+        let request = Request::Sign {
+            message: hex::encode(message),
+        };
+        let signature = call(&self.path, &request)?
+            .signature
+            .context("External signer did not return a signature")?;
+        recoverable(
+            &self.verifying_key,
+            message,
+            Signature::from_slice(&hex::decode(signature)?)?,
+        )
+    }
+}
+
+/// Send a single request to the signer at `path`.
+fn call(path: &Path, request: &Request) -> anyhow::Result<Response> {
+    // Signing happens in the middle of request handlers, don't hold up
+    // other tasks on this worker while we wait.
+    tokio::task::block_in_place(|| {
+        let mut stream = UnixStream::connect(path)
+            .with_context(|| format!("Unable to connect to signer at {}", path.display()))?;
+        stream.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+        stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+        let mut line = serde_json::to_string(request)?;
+        line.push('\n');
+        stream.write_all(line.as_bytes())?;
+        line.clear();
+        BufReader::new(stream).read_line(&mut line)?;
+        let response: Response =
+            serde_json::from_str(&line).context("Invalid response from external signer")?;
+        match response.error {
+            Some(error) => anyhow::bail!("External signer failed: {error}"),
+            None => Ok(response),
+        }
+    })
+}
+
+/// Serve signatures with `key` on a Unix socket at `path`.
+///
+/// Connections are handled one at a time. The socket is only accessible
+/// to the current user, so run the server as the same user.
+pub(crate) fn serve(path: &Path, key: &SigningKey) -> anyhow::Result<()> {
+    remove_stale_socket(path)?;
+    // Create the socket as 0600 straight away. Changing its permissions
+    // after binding would leave a window where anyone could connect.
+    // SAFETY: umask can't fail, and nothing else creates files meanwhile.
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(path);
+    unsafe { libc::umask(umask) };
+    let listener = listener.with_context(|| format!("Unable to listen on {}", path.display()))?;
+    println!(
+        "Signing with {} on {}",
+        hex::encode(key.verifying_key().to_sec1_bytes()),
+        path.display()
+    );
+    for stream in listener.incoming() {
+        let handled = stream
+            .map_err(anyhow::Error::from)
+            .and_then(|stream| handle(stream, key));
+        if let Err(e) = handled {
+            eprintln!("Signer request failed: {e:#}");
+        }
+    }
+    Ok(())
+}
+
+/// Remove a socket left behind by a signer that didn't shut down cleanly.
+///
+/// Fails if another signer is still listening on it, or if the path is
+/// anything other than a socket.
+fn remove_stale_socket(path: &Path) -> anyhow::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Unable to check {}", path.display())),
+    };
+    anyhow::ensure!(
+        std::os::unix::fs::FileTypeExt::is_socket(&metadata.file_type()),
+        "{} already exists and is not a socket, refusing to replace it",
+        path.display()
+    );
+    anyhow::ensure!(
+        UnixStream::connect(path).is_err(),
+        "Another signer is already listening on {}",
+        path.display()
+    );
+    std::fs::remove_file(path)
+        .with_context(|| format!("Unable to remove stale socket {}", path.display()))
+}
+
+fn handle(stream: UnixStream, key: &SigningKey) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(SOCKET_TIMEOUT))?;
+    stream.set_write_timeout(Some(SOCKET_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let response = match serde_json::from_str(&line) {
+        Ok(Request::PublicKey) => Response {
+            public_key: Some(hex::encode(key.verifying_key().to_sec1_bytes())),
+            ..Response::default()
+        },
+        Ok(Request::Sign { message }) => match hex::decode(message) {
+            Ok(message) => match key.sign_recoverable(&message) {
+                Ok((signature, _recovery_id)) => Response {
+                    signature: Some(hex::encode(signature.to_bytes())),
+                    ..Response::default()
+                },
+                Err(e) => {
+                    eprintln!("Signing failed: {e}");
+                    Response::error(format!("Signing failed: {e}"))
+                }
+            },
+            Err(e) => Response::error(format!("Invalid message: {e}")),
+        },
+        Err(e) => Response::error(format!("Invalid request: {e}")),
+    };
+    let mut line = serde_json::to_string(&response)?;
+    line.push('\n');
+    reader.into_inner().write_all(line.as_bytes())?;
+    Ok(())
+}
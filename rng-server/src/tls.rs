@@ -0,0 +1,83 @@
+//! TLS termination from certificate and key files.
+//!
+//! The files are checked for changes every [POLL_INTERVAL] and reloaded
+//! in place, so a renewed certificate is picked up without a restart.
+//! New connections use the new certificate, open ones keep the old one.
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(clap::Args)]
+pub(crate) struct TlsOpts {
+    /// Serve HTTPS with the PEM certificate chain in this file.
+    ///
+    /// Reloaded whenever it or the key changes.
+    #[clap(long, env = "RARE_EVO_TLS_CERT", requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+    /// PEM private key for `--tls-cert`.
+    #[clap(long, env = "RARE_EVO_TLS_KEY", requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
+}
+
+impl TlsOpts {
+    /// Load the certificate and start watching it, [None] to serve plain HTTP.
+    pub(crate) async fn load(self) -> anyhow::Result<Option<RustlsConfig>> {
+        let (Some(cert), Some(key)) = (self.tls_cert, self.tls_key) else {
+            return Ok(None);
+        };
+        // Fails if another provider was installed first, which is just as good.
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        // Read before loading, so a change in between gets reloaded.
+        let modified = modified(&cert, &key)?;
+        let config = RustlsConfig::from_pem_file(&cert, &key)
+            .await
+            .with_context(|| format!("Unable to load TLS certificate {}", cert.display()))?;
+        println!("Serving HTTPS with the certificate in {}", cert.display());
+        tokio::spawn(watch(config.clone(), cert, key, modified));
+        Ok(Some(config))
+    }
+}
+
+/// Modification times of the certificate and key.
+fn modified(cert: &Path, key: &Path) -> anyhow::Result<(SystemTime, SystemTime)> {
+    let modified = |path: &Path| {
+        path.metadata()
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Unable to read {}", path.display()))
+    };
+    Ok((modified(cert)?, modified(key)?))
+}
+
+async fn watch(
+    config: RustlsConfig,
+    cert: PathBuf,
+    key: PathBuf,
+    mut last: (SystemTime, SystemTime),
+) {
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let current = match modified(&cert, &key) {
+            Ok(current) => current,
+            Err(e) => {
+                eprintln!("Unable to check TLS certificate for changes: {e:#}");
+                continue;
+            }
+        };
+        if current == last {
+            continue;
+        }
+        // The two files are usually replaced one after the other. If we see
+        // a mismatched pair it fails to load, and we retry on the next poll.
+        match config.reload_from_pem_file(&cert, &key).await {
+            Ok(()) => {
+                println!("Reloaded TLS certificate from {}", cert.display());
+                last = current;
+            }
+            Err(e) => eprintln!("Unable to reload TLS certificate, keeping the old one: {e}"),
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Offline checks of saved `/number/{timestamp}`, `/numbers` and
+//! `/bundle/{date}` responses.
+use std::path::PathBuf;
+
+use anyhow::Context;
+use k256::ecdsa::VerifyingKey;
+use rng_protocol::{RngBundle, RngResult, SeedCheck, Signed, Verified};
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+/// A single round, a whole `/numbers` range or a day's bundle.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Saved {
+    Bundle(RngBundle),
+    One(Signed<RngResult>),
+    Many(Vec<Signed<RngResult>>),
+}
+
+/// Check every response in the input, failing on the first bad one.
+///
+/// `input` is a path, or stdin if [None] or `-`.
+pub(crate) fn run(
+    public_keys: &[VerifyingKey],
+    input: Option<PathBuf>,
+    hmac_secret: Option<Zeroizing<String>>,
+) -> anyhow::Result<()> {
+    let contents = match input {
+        Some(path) if path.as_os_str() != "-" => std::fs::read_to_string(&path)
+            .with_context(|| format!("Unable to read {}", path.display()))?,
+        _ => std::io::read_to_string(std::io::stdin()).context("Unable to read stdin")?,
+    };
+    let hmac_key = hmac_secret
+        .map(|secret| hex::decode(&*secret).map(Zeroizing::new))
+        .transpose()?;
+    let hmac_key = hmac_key.as_deref().map(Vec::as_slice);
+    let responses = match serde_json::from_str(&contents).context("Not an rng-server response")? {
+        Saved::Bundle(bundle) => {
+            let verified = rng_protocol::verify_bundle(&bundle, public_keys, hmac_key)
+                .context("Bundle failed verification")?;
+            verified.iter().for_each(report);
+            println!(
+                "Bundle for {}: {} rounds, Merkle root {} OK (signed by {})",
+                bundle.root.fields.date,
+                verified.len(),
+                bundle.root.fields.root,
+                hex::encode(bundle.root.signer()?.to_sec1_bytes())
+            );
+            return Ok(());
+        }
+        Saved::One(response) => vec![response],
+        Saved::Many(responses) => responses,
+    };
+    for response in &responses {
+        let verified = rng_protocol::verify(response, public_keys, hmac_key)
+            .with_context(|| format!("Round {} failed verification", response.fields.timestamp))?;
+        report(&verified);
+    }
+    Ok(())
+}
+
+/// Print what was checked for a single round.
+fn report(verified: &Verified) {
+    let mut checks = vec![format!(
+        "signed by {}",
+        hex::encode(verified.signer.to_sec1_bytes())
+    )];
+    match verified.seed {
+        SeedCheck::Vrf => checks.push("VRF proof valid".to_owned()),
+        SeedCheck::Hmac => checks.push("HMAC recomputed".to_owned()),
+        SeedCheck::Unchecked => (),
+    }
+    println!(
+        "Round {}: number {} OK ({})",
+        verified.result.timestamp,
+        verified.result.number,
+        checks.join(", ")
+    );
+}